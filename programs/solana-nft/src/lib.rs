@@ -1,11 +1,31 @@
 use {
     anchor_lang::{
         prelude::*, system_program, solana_program::program::invoke, solana_program::program::invoke_signed,
+        solana_program::keccak, solana_program::sysvar,
     },
-    anchor_spl::{token, token::Token, associated_token},
-    mpl_token_metadata::instruction::{
-        create_master_edition_v3, create_metadata_accounts_v3, update_metadata_accounts_v2,
-        approve_collection_authority, set_and_verify_collection, sign_metadata, 
+    anchor_spl::{
+        token, token_interface, token_interface::{Mint, TokenAccount, TokenInterface}, associated_token,
+    },
+    mpl_token_metadata::{
+        instruction::{
+            create_master_edition_v3, create_metadata_accounts_v3, update_metadata_accounts_v2,
+            approve_collection_authority, set_and_verify_collection, sign_metadata, 
+            mint_new_edition_from_master_edition_via_token, freeze_delegated_account, thaw_delegated_account,
+            builders::{
+                BurnBuilder, CreateBuilder, DelegateBuilder, MintBuilder, PrintBuilder, RevokeBuilder, TransferBuilder,
+                UpdateBuilder, VerifyBuilder,
+            },
+            BurnArgs, CollectionDetailsToggle, CollectionToggle, CreateArgs, DelegateArgs, InstructionBuilder, MintArgs,
+            PrintArgs, RevokeArgs, RuleSetToggle, TransferArgs, UpdateArgs, UsesToggle, VerificationArgs,
+        },
+        pda::{
+            find_collection_authority_account, find_edition_account, find_master_edition_account,
+            find_metadata_account, find_token_record_account,
+        },
+        state::{
+            AssetData, Collection, Data, Metadata, MasterEditionV2, PrintSupply, TokenMetadataAccount, TokenStandard,
+            EDITION_MARKER_BIT_SIZE, MAX_CREATOR_LIMIT, MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH, MAX_URI_LENGTH,
+        },
     }
 };
 
@@ -13,7 +33,22 @@ const DISCRIMINATOR_LENGTH: usize = 8;
 const PUBLIC_KEY_LENGTH: usize = 32;
 const STRING_PREFIX_LENGTH: usize = 4;
 const I64_LENGTH: usize = 8;
+const U64_LENGTH: usize = 8;
+const U32_LENGTH: usize = 4;
+const U16_LENGTH: usize = 2;
 const U8_LENGTH: usize = 1;
+const BOOL_LENGTH: usize = 1;
+const VEC_PREFIX_LENGTH: usize = 4;
+const OPTION_PREFIX_LENGTH: usize = 1;
+const HASH_LENGTH: usize = 32;
+
+const MAX_BASIS_POINTS: u16 = 10000;
+const MAX_MINT_PHASES: usize = 5;
+const MAX_PAYMENT_MINTS: usize = 10;
+const MAX_AUCTION_EXTENSION: i64 = 24 * 60 * 60;
+const CIDV0_LENGTH: usize = 46;
+const CIDV1_MIN_LENGTH: usize = 59;
+const MAX_IPFS_HASH_LENGTH: usize = 64;
 
 declare_id!("756vNUvmmyKCimqoQGtHAMexYsadsDsPG5ieL2KXfbfp");
 
@@ -21,33 +56,50 @@ declare_id!("756vNUvmmyKCimqoQGtHAMexYsadsDsPG5ieL2KXfbfp");
 pub mod solana_nft {
     use super::*;
 
+    // The collection metadata alone takes 7 arguments, the pNFT options are grouped in ProgrammableConfig
+    #[allow(clippy::too_many_arguments)]
     pub fn mint_collection(
         ctx: Context<MintCollection>,
         collection_name: String,
         collection_symbol: String,
         ipfs_image_hash: String,
         metadata_uri: String,
+        seller_fee_basis_points: u16,
+        creators: Vec<CollectionCreator>,
+        programmable_config: ProgrammableConfig,
     ) -> Result<()> {
+        let ProgrammableConfig { programmable, rule_set } = programmable_config;
+        validate_metadata(&collection_name, &collection_symbol, &metadata_uri, &ipfs_image_hash)?;
+        require!(programmable || rule_set.is_none(), MarketplaceError::InvalidRuleSet);
+        require!(seller_fee_basis_points <= MAX_BASIS_POINTS, MarketplaceError::InvalidBasisPoints);
+        require!(
+            !creators.is_empty() && creators.len() <= MAX_CREATOR_LIMIT,
+            MarketplaceError::InvalidCreators
+        );
+        require!(
+            creators.iter().map(|c| c.share as u16).sum::<u16>() == 100,
+            MarketplaceError::InvalidCreatorShares
+        );
 
         // Create an account to become it in the collection token_mint 
         system_program::create_account(
             CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
                 system_program::CreateAccount {
                     from: ctx.accounts.mint_authority.to_account_info(),
                     to: ctx.accounts.mint.to_account_info(),
                 },
             ),
-            1461600,
-            82,
+            ctx.accounts.rent.minimum_balance(token::Mint::LEN),
+            token::Mint::LEN as u64,
             &ctx.accounts.token_program.key(),
         )?;
 
         // Create the token_mint for the collection
-        token::initialize_mint(
+        token_interface::initialize_mint(
             CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
-                token::InitializeMint {
+                token_interface::InitializeMint {
                     mint: ctx.accounts.mint.to_account_info(),
                     rent: ctx.accounts.rent.to_account_info(),
                 },
@@ -73,10 +125,10 @@ pub mod solana_nft {
         )?;
 
         // Mint collection token
-        token::mint_to(
+        token_interface::mint_to(
             CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
-                token::MintTo {
+                token_interface::MintTo {
                     mint: ctx.accounts.mint.to_account_info(),
                     to: ctx.accounts.token_account.to_account_info(),
                     authority: ctx.accounts.mint_authority.to_account_info(),
@@ -86,14 +138,6 @@ pub mod solana_nft {
         )?;
 
         // Create metadata for the collection token_mint
-        let creators = vec![
-            mpl_token_metadata::state::Creator {
-                address: ctx.accounts.mint_authority.key(),
-                verified: false,
-                share: 100,
-            }
-        ];
-
         invoke(
             &create_metadata_accounts_v3(
                 ctx.accounts.token_metadata_program.key(),
@@ -105,8 +149,8 @@ pub mod solana_nft {
                 collection_name.clone(),
                 collection_symbol.clone(),
                 metadata_uri,
-                Some(creators),
-                0,
+                Some(CollectionCreator::to_metaplex(&creators)),
+                seller_fee_basis_points,
                 true,
                 true,
                 None,
@@ -125,18 +169,20 @@ pub mod solana_nft {
             ],
         )?;
 
-        // Sign metadata transaction
-        invoke(
-            &sign_metadata(
-                ctx.accounts.token_metadata_program.key(),
-                ctx.accounts.metadata.key(),
-                ctx.accounts.mint_authority.key(),
-            ),
-            &[
-                ctx.accounts.metadata.to_account_info(),
-                ctx.accounts.mint_authority.to_account_info(),
-            ],
-        )?;
+        // Sign metadata transaction if mint_authority is one of the creators
+        if creators.iter().any(|c| c.address == ctx.accounts.mint_authority.key()) {
+            invoke(
+                &sign_metadata(
+                    ctx.accounts.token_metadata_program.key(),
+                    ctx.accounts.metadata.key(),
+                    ctx.accounts.mint_authority.key(),
+                ),
+                &[
+                    ctx.accounts.metadata.to_account_info(),
+                    ctx.accounts.mint_authority.to_account_info(),
+                ],
+            )?;
+        }
 
         // Create master edition for the collection
         invoke(
@@ -195,7 +241,7 @@ pub mod solana_nft {
                 Some(ctx.accounts.collection_pda.key()),
                 None,
                 Some(true),
-                Some(false),
+                Some(true),
             ),
             &[
                 ctx.accounts.token_metadata_program.to_account_info(),
@@ -205,14 +251,18 @@ pub mod solana_nft {
         )?;
 
         // Set custom data collection account
-        let clock: Clock = Clock::get().unwrap();
+        let clock: Clock = Clock::get()?;
 
         ctx.accounts.collection_pda.owner = ctx.accounts.payer.key();
         ctx.accounts.collection_pda.token_mint = ctx.accounts.mint.key();
         ctx.accounts.collection_pda.name = collection_name;
         ctx.accounts.collection_pda.symbol = collection_symbol;
         ctx.accounts.collection_pda.ipfs_image_hash = ipfs_image_hash;
-        ctx.accounts.collection_pda.bump = *ctx.bumps.get("collection_pda").unwrap();
+        ctx.accounts.collection_pda.seller_fee_basis_points = seller_fee_basis_points;
+        ctx.accounts.collection_pda.creators = creators;
+        ctx.accounts.collection_pda.programmable = programmable;
+        ctx.accounts.collection_pda.rule_set = rule_set;
+        ctx.accounts.collection_pda.bump = *ctx.bumps.get("collection_pda").ok_or(MarketplaceError::MissingBump)?;
         ctx.accounts.collection_pda.created = clock.unix_timestamp;
 
         Ok(())
@@ -223,421 +273,5671 @@ pub mod solana_nft {
         nft_name: String,
         ipfs_image_hash: String,
         metadata_uri: String,
+        edition_max_supply: Option<u64>,
+        edition_price: u64,
     ) -> Result<()> {
+        // Only the collection owner and the minters it granted rights to can mint
+        require!(
+            ctx.accounts.mint_authority.key() == ctx.accounts.collection_pda.owner
+                || ctx.accounts.minter_pda.is_some(),
+            MarketplaceError::NotAllowedToMint
+        );
 
-        // Create an account to become it in the NFT token_mint
-        system_program::create_account(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                system_program::CreateAccount {
-                    from: ctx.accounts.mint_authority.to_account_info(),
-                    to: ctx.accounts.mint.to_account_info(),
-                },
-            ),
-            1461600,
-            82,
-            &ctx.accounts.token_program.key(),
-        )?;
+        mint_nft(
+            ctx.accounts,
+            nft_name,
+            ipfs_image_hash,
+            metadata_uri,
+            edition_max_supply,
+            edition_price,
+        )
+    }
 
-        // Create the token_mint for the NFT
-        token::initialize_mint(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                token::InitializeMint {
-                    mint: ctx.accounts.mint.to_account_info(),
-                    rent: ctx.accounts.rent.to_account_info(),
-                },
-            ),
-            0,
-            &ctx.accounts.mint_authority.key(),
-            Some(&ctx.accounts.mint_authority.key()),
-        )?;
+    pub fn configure_mint_phases(
+        ctx: Context<ConfigureMintPhases>,
+        max_supply: u64,
+        phases: Vec<MintPhase>,
+    ) -> Result<()> {
+        require!(phases.len() <= MAX_MINT_PHASES, MarketplaceError::TooManyMintPhases);
+        require!(
+            phases.iter().all(|phase| phase.end_time > phase.start_time),
+            MarketplaceError::InvalidMintWindow
+        );
+        require!(
+            max_supply == 0 || max_supply >= ctx.accounts.collection_pda.minted_count,
+            MarketplaceError::InvalidMaxSupply
+        );
+        for phase in phases.iter() {
+            assert_payment_mint(&ctx.accounts.marketplace, &phase.payment_mint)?;
+        }
 
-        // Create ATA for mint_authority
-        associated_token::create(CpiContext::new(
-            ctx.accounts.associated_token_program.to_account_info(),
-            associated_token::Create {
-                payer: ctx.accounts.mint_authority.to_account_info(),
-                associated_token: ctx.accounts.token_account.to_account_info(),
-                authority: ctx.accounts.mint_authority.to_account_info(),
-                mint: ctx.accounts.mint.to_account_info(),
-                system_program: ctx.accounts.system_program.to_account_info(),
-                token_program: ctx.accounts.token_program.to_account_info(),
-            },
-        ))?;
+        // Bumping phases_version resets the per wallet counts of the previous phases
+        ctx.accounts.collection_pda.max_supply = max_supply;
+        ctx.accounts.collection_pda.mint_phases = phases;
+        ctx.accounts.collection_pda.phases_version = ctx.accounts.collection_pda.phases_version
+            .checked_add(1)
+            .ok_or(MarketplaceError::MathOverflow)?;
 
-        // Mint NFT
-        token::mint_to(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                token::MintTo {
-                    mint: ctx.accounts.mint.to_account_info(),
-                    to: ctx.accounts.token_account.to_account_info(),
-                    authority: ctx.accounts.mint_authority.to_account_info(),
-                },
+        Ok(())
+    }
+
+    pub fn public_mint_nft(
+        ctx: Context<PublicMintNft>,
+        nft_name: String,
+        ipfs_image_hash: String,
+        metadata_uri: String,
+        phase_index: u8,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let clock: Clock = Clock::get()?;
+        let phase = ctx.accounts.mint_nft.collection_pda.mint_phases
+            .get(phase_index as usize)
+            .cloned()
+            .ok_or(MarketplaceError::InvalidMintPhase)?;
+
+        require!(
+            clock.unix_timestamp >= phase.start_time && clock.unix_timestamp < phase.end_time,
+            MarketplaceError::MintNotActive
+        );
+
+        // Allowlist phases need a proof that the minter is part of the Merkle tree
+        let minter = ctx.accounts.mint_nft.mint_authority.key();
+        if let Some(merkle_root) = phase.merkle_root {
+            let leaf = keccak::hash(minter.as_ref()).0;
+            require!(
+                verify_merkle_proof(&proof, merkle_root, leaf),
+                MarketplaceError::NotOnAllowlist
+            );
+        }
+
+        // Counts minted under previous phases don't apply once the phases are reconfigured
+        let wallet_mints = &mut ctx.accounts.wallet_mints_pda;
+        let phases_version = ctx.accounts.mint_nft.collection_pda.phases_version;
+        if wallet_mints.phases_version != phases_version {
+            wallet_mints.phase_mints = [0; MAX_MINT_PHASES];
+            wallet_mints.phases_version = phases_version;
+        }
+
+        // A per_wallet_limit of 0 means wallets can mint without limit
+        let minted = wallet_mints.phase_mints[phase_index as usize];
+        require!(
+            phase.per_wallet_limit == 0 || minted < phase.per_wallet_limit,
+            MarketplaceError::WalletLimitReached
+        );
+        wallet_mints.wallet = minter;
+        wallet_mints.collection = ctx.accounts.mint_nft.collection_pda.key();
+        wallet_mints.phase_mints[phase_index as usize] = minted + 1;
+        wallet_mints.bump = *ctx.bumps.get("wallet_mints_pda").ok_or(MarketplaceError::MissingBump)?;
+
+        // Pay the mint price to the collection owner
+        let (from, owner_account) = match phase.payment_mint {
+            None => (
+                ctx.accounts.mint_nft.mint_authority.to_account_info(),
+                ctx.accounts.collection_owner.to_account_info(),
             ),
-            1,
+            Some(_) => (
+                payment_account(&ctx.accounts.minter_payment_account)?,
+                payment_account(&ctx.accounts.owner_payment_account)?,
+            ),
+        };
+        let payment = Payment {
+            payment_mint: payment_mint_account(phase.payment_mint, &ctx.accounts.payment_mint)?,
+            from,
+            authority: ctx.accounts.mint_nft.mint_authority.to_account_info(),
+            signer_seeds: &[],
+            system_program: &ctx.accounts.mint_nft.system_program,
+            token_program: &ctx.accounts.mint_nft.token_program,
+        };
+        payment.pay(&owner_account, &ctx.accounts.collection_owner.key(), phase.price)?;
+
+        // Public mints can't be printed
+        mint_nft(&mut ctx.accounts.mint_nft, nft_name, ipfs_image_hash, metadata_uri, Some(0), 0)
+    }
+
+    pub fn update_collection(
+        ctx: Context<UpdateCollection>,
+        collection_name: String,
+        ipfs_image_hash: String,
+        metadata_uri: String,
+    ) -> Result<()> {
+        validate_metadata(
+            &collection_name,
+            &ctx.accounts.collection_pda.symbol,
+            &metadata_uri,
+            &ipfs_image_hash,
         )?;
 
-        // Create metadata for the NFT token_mint
-        invoke(
-            &create_metadata_accounts_v3(
-                ctx.accounts.token_metadata_program.key(),
-                ctx.accounts.metadata.key(),
-                ctx.accounts.mint.key(),
-                ctx.accounts.mint_authority.key(),
-                ctx.accounts.payer.key(),
-                ctx.accounts.payer.key(),
-                nft_name.clone(),
-                ctx.accounts.collection_pda.symbol.to_string(),
-                metadata_uri.clone(),
-                None,
-                0,
-                true,
-                true,
-                None,
-                None,
-                None,
-            ),
-            &[
-                ctx.accounts.metadata.to_account_info(),
-                ctx.accounts.mint.to_account_info(),
-                ctx.accounts.mint_authority.to_account_info(),
-                ctx.accounts.payer.to_account_info(),
-                ctx.accounts.token_metadata_program.to_account_info(),
-                ctx.accounts.token_program.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-                ctx.accounts.rent.to_account_info(),
-            ],
+        update_metadata(
+            &ctx.accounts.metadata,
+            &ctx.accounts.collection_pda,
+            &ctx.accounts.token_metadata_program,
+            collection_name.clone(),
+            metadata_uri,
         )?;
 
-        // Create master edition for the NFT
-        invoke(
-            &create_master_edition_v3(
-                ctx.accounts.token_metadata_program.key(),
-                ctx.accounts.master_edition.key(),
-                ctx.accounts.mint.key(),
-                ctx.accounts.payer.key(),
-                ctx.accounts.mint_authority.key(),
-                ctx.accounts.metadata.key(),
-                ctx.accounts.payer.key(),
-                Some(0),
-            ),
-            &[
-                ctx.accounts.master_edition.to_account_info(),
-                ctx.accounts.mint.to_account_info(),
-                ctx.accounts.mint_authority.to_account_info(),
-                ctx.accounts.payer.to_account_info(),
-                ctx.accounts.metadata.to_account_info(),
-                ctx.accounts.token_metadata_program.to_account_info(),
-                ctx.accounts.token_program.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-                ctx.accounts.rent.to_account_info(),
-            ],
+        ctx.accounts.collection_pda.name = collection_name;
+        ctx.accounts.collection_pda.ipfs_image_hash = ipfs_image_hash;
+
+        Ok(())
+    }
+
+    pub fn update_nft(
+        ctx: Context<UpdateNft>,
+        nft_name: String,
+        ipfs_image_hash: String,
+        metadata_uri: String,
+    ) -> Result<()> {
+        validate_metadata(
+            &nft_name,
+            &ctx.accounts.collection_pda.symbol,
+            &metadata_uri,
+            &ipfs_image_hash,
         )?;
 
-        // Change update authority to nft_pda and set metadata
-        let creators = vec![
-            mpl_token_metadata::state::Creator {
-                address: ctx.accounts.mint_authority.key(),
-                verified: false,
-                share: 100,
+        if ctx.accounts.collection_pda.programmable {
+            let current = Metadata::from_account_info(&ctx.accounts.metadata)?;
+            let data = Data {
+                name: nft_name.clone(),
+                symbol: ctx.accounts.collection_pda.symbol.clone(),
+                uri: metadata_uri,
+                seller_fee_basis_points: current.data.seller_fee_basis_points,
+                creators: current.data.creators,
+            };
+
+            // Update the pNFT metadata with Metaplex Update, the legacy update rejects pNFTs
+            ProgrammableUpdate {
+                collection_pda: &ctx.accounts.collection_pda,
+                payer: ctx.accounts.owner.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions: programmable_account(&ctx.accounts.sysvar_instructions)?,
+                token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+                authorization_rules: authorization_rules_accounts(
+                    &ctx.accounts.authorization_rules_program,
+                    &ctx.accounts.authorization_rules,
+                )?,
             }
-        ];
+            .invoke(
+                &ctx.accounts.metadata,
+                &programmable_account(&ctx.accounts.mint)?,
+                programmable_update_args(None, Some(data)),
+            )?;
+        } else {
+            update_metadata(
+                &ctx.accounts.metadata,
+                &ctx.accounts.collection_pda,
+                &ctx.accounts.token_metadata_program,
+                nft_name.clone(),
+                metadata_uri,
+            )?;
+        }
 
-        let data = mpl_token_metadata::state::DataV2 {
-            name: nft_name.clone(),
-            symbol: ctx.accounts.collection_pda.symbol.clone(),
-            uri: metadata_uri.clone(),
-            collection: None,
-            creators: Some(creators),
-            seller_fee_basis_points: 0,
-            uses: None,
-        };
+        ctx.accounts.nft_pda.name = nft_name;
+        ctx.accounts.nft_pda.ipfs_image_hash = ipfs_image_hash;
 
-        invoke(
-            &update_metadata_accounts_v2(
-                ctx.accounts.token_metadata_program.key(),
-                ctx.accounts.metadata.key(),
-                ctx.accounts.payer.key(),
-                Some(ctx.accounts.collection_pda.key()),
-                Some(data),
-                Some(true),
-                Some(true),
-            ),
-            &[
-                ctx.accounts.token_metadata_program.to_account_info(),
-                ctx.accounts.metadata.to_account_info(),
-                ctx.accounts.payer.to_account_info(),
-            ],
-        )?;
+        Ok(())
+    }
+
+    pub fn propose_collection_owner(
+        ctx: Context<ProposeCollectionOwner>,
+        new_owner: Option<Pubkey>,
+    ) -> Result<()> {
+        // None cancels a pending proposal
+        ctx.accounts.collection_pda.pending_owner = new_owner;
+
+        Ok(())
+    }
+
+    pub fn accept_collection_owner(ctx: Context<AcceptCollectionOwner>) -> Result<()> {
+        ctx.accounts.collection_pda.owner = ctx.accounts.new_owner.key();
+        ctx.accounts.collection_pda.pending_owner = None;
+
+        Ok(())
+    }
+
+    pub fn grant_minting_rights(ctx: Context<GrantMintingRights>) -> Result<()> {
+        let clock: Clock = Clock::get()?;
+
+        ctx.accounts.minter_pda.collection = ctx.accounts.collection_pda.key();
+        ctx.accounts.minter_pda.minter = ctx.accounts.minter.key();
+        ctx.accounts.minter_pda.granted_by = ctx.accounts.owner.key();
+        ctx.accounts.minter_pda.bump = *ctx.bumps.get("minter_pda").ok_or(MarketplaceError::MissingBump)?;
+        ctx.accounts.minter_pda.created = clock.unix_timestamp;
+
+        Ok(())
+    }
+
+    pub fn revoke_minting_rights(_ctx: Context<RevokeMintingRights>) -> Result<()> {
+        // minter_pda is closed by its account constraint
+        Ok(())
+    }
 
-        // Sign Metadata (verify creator)
+    pub fn release_update_authority<'info>(
+        ctx: Context<'_, '_, '_, 'info, ReleaseUpdateAuthority<'info>>,
+        new_update_authority: Pubkey,
+    ) -> Result<()> {
         let coll_mint = ctx.accounts.collection_pda.token_mint;
         let coll_bump = ctx.accounts.collection_pda.bump;
-        let _signer_seeds = [
+        let signer_seeds = [
             b"collection".as_ref(),
             coll_mint.as_ref(),
             &[coll_bump],
         ];
 
-        invoke_signed(
-            &sign_metadata(
-                ctx.accounts.token_metadata_program.key(),
-                ctx.accounts.metadata.key(),
-                ctx.accounts.mint_authority.key(),
-            ),
-            &[
-                ctx.accounts.metadata.to_account_info(),
-                ctx.accounts.mint_authority.to_account_info(),
-            ],
-            &[&_signer_seeds],
-        )?;
+        // The collection metadata comes first on the first release, the metadata of NFTs
+        // to release are passed as remaining accounts so later calls can release them in batches.
+        // Metaplex rejects legacy updates of pNFTs, so in programmable collections each NFT
+        // metadata is followed by its mint for Metaplex Update, the collection NFT stays legacy
+        let nft_accounts_len = if ctx.accounts.collection_pda.programmable { 2 } else { 1 };
+        let nft_accounts = ctx.remaining_accounts.chunks_exact(nft_accounts_len);
+        require!(nft_accounts.remainder().is_empty(), MarketplaceError::MissingProgrammableAccount);
+
+        let mut metadata_accounts = vec![];
+        let mut programmable_accounts = vec![];
+        if !ctx.accounts.collection_pda.update_authority_released {
+            metadata_accounts.push(ctx.accounts.metadata.to_account_info());
+        }
+        for nft_accounts in nft_accounts {
+            let nft_metadata = &nft_accounts[0];
+            require_keys_eq!(
+                *nft_metadata.owner,
+                mpl_token_metadata::ID,
+                MarketplaceError::WrongMetadataAccount
+            );
+            assert_verified_collection(nft_metadata, &coll_mint)?;
+            match nft_accounts.get(1) {
+                Some(nft_mint) => programmable_accounts.push((nft_metadata.clone(), nft_mint.clone())),
+                None => metadata_accounts.push(nft_metadata.clone()),
+            }
+        }
+
+        // Change update authority from collection_pda to the new wallet
+        for metadata in metadata_accounts.iter() {
+            invoke_signed(
+                &update_metadata_accounts_v2(
+                    ctx.accounts.token_metadata_program.key(),
+                    metadata.key(),
+                    ctx.accounts.collection_pda.key(),
+                    Some(new_update_authority),
+                    None,
+                    None,
+                    None,
+                ),
+                &[
+                    ctx.accounts.token_metadata_program.to_account_info(),
+                    metadata.clone(),
+                    ctx.accounts.collection_pda.to_account_info(),
+                ],
+                &[&signer_seeds],
+            )?;
+        }
+
+        if !programmable_accounts.is_empty() {
+            let programmable_update = ProgrammableUpdate {
+                collection_pda: &ctx.accounts.collection_pda,
+                payer: ctx.accounts.owner.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions: programmable_account(&ctx.accounts.sysvar_instructions)?,
+                token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+                authorization_rules: authorization_rules_accounts(
+                    &ctx.accounts.authorization_rules_program,
+                    &ctx.accounts.authorization_rules,
+                )?,
+            };
+
+            // Change update authority of the pNFTs with Metaplex Update, Metaplex checks each mint
+            for (nft_metadata, nft_mint) in programmable_accounts.iter() {
+                programmable_update.invoke(
+                    nft_metadata,
+                    nft_mint,
+                    programmable_update_args(Some(new_update_authority), None),
+                )?;
+            }
+        }
+
+        ctx.accounts.collection_pda.update_authority_released = true;
+
+        Ok(())
+    }
+
+    pub fn lock_collection(ctx: Context<LockCollection>) -> Result<()> {
+        let coll_mint = ctx.accounts.collection_pda.token_mint;
+        let coll_bump = ctx.accounts.collection_pda.bump;
+        let signer_seeds = [
+            b"collection".as_ref(),
+            coll_mint.as_ref(),
+            &[coll_bump],
+        ];
 
-        // Verify master edition
+        // Make the collection metadata immutable
         invoke_signed(
-            &set_and_verify_collection(
+            &update_metadata_accounts_v2(
                 ctx.accounts.token_metadata_program.key(),
                 ctx.accounts.metadata.key(),
                 ctx.accounts.collection_pda.key(),
-                ctx.accounts.payer.key(),
-                ctx.accounts.collection_pda.key(),
-                ctx.accounts.collection_token_mint.key(),
-                ctx.accounts.collection_metadata.key(),
-                ctx.accounts.collection_master_ed.key(),
                 None,
+                None,
+                None,
+                Some(false),
             ),
             &[
+                ctx.accounts.token_metadata_program.to_account_info(),
                 ctx.accounts.metadata.to_account_info(),
                 ctx.accounts.collection_pda.to_account_info(),
-                ctx.accounts.payer.to_account_info(),
-                ctx.accounts.collection_pda.to_account_info(),
-                ctx.accounts.collection_token_mint.to_account_info(),
-                ctx.accounts.collection_metadata.to_account_info(),
-                ctx.accounts.collection_master_ed.to_account_info(),
             ],
-            &[&_signer_seeds],
+            &[&signer_seeds],
         )?;
 
-        // Set NFT data
-        let clock: Clock = Clock::get().unwrap();
-
-        ctx.accounts.nft_pda.token_mint = ctx.accounts.mint.key();
-        ctx.accounts.nft_pda.collection_mint = ctx.accounts.collection_token_mint.key();
-        ctx.accounts.nft_pda.name = nft_name;
-        ctx.accounts.nft_pda.ipfs_image_hash = ipfs_image_hash;
-        ctx.accounts.nft_pda.created = clock.unix_timestamp;
+        // NFTs minted from now on are immutable too
+        ctx.accounts.collection_pda.locked = true;
 
         Ok(())
     }
 
-}
+    pub fn sign_creator(ctx: Context<SignCreator>) -> Result<()> {
+        // Creators can verify the collection itself or any NFT verified in it
+        let coll_mint = ctx.accounts.collection_pda.token_mint;
+        if ctx.accounts.metadata.key() != find_metadata_account(&coll_mint).0 {
+            require_keys_eq!(
+                *ctx.accounts.metadata.owner,
+                mpl_token_metadata::ID,
+                MarketplaceError::WrongMetadataAccount
+            );
+            assert_verified_collection(&ctx.accounts.metadata, &coll_mint)?;
+        }
 
-#[derive(Accounts)]
-#[instruction(
-    collection_name: String,
-    collection_symbol: String,
-    ipfs_image_hash: String,
-    _metadata_uri: String,
-)]
-pub struct MintCollection<'info> {
-    /// CHECK:
-    #[account(mut)]
-    pub mint: Signer<'info>,
+        // Verify creator, Verify works for legacy NFTs and pNFTs alike
+        let verify_ix = VerifyBuilder::new()
+            .authority(ctx.accounts.creator.key())
+            .metadata(ctx.accounts.metadata.key())
+            .build(VerificationArgs::CreatorV1)
+            .map_err(|_| error!(MarketplaceError::MetadataInstructionFailed))?
+            .instruction();
 
-    /// CHECK:
-    #[account(mut)]
-    pub mint_authority: Signer<'info>,
+        invoke(
+            &verify_ix,
+            &[
+                ctx.accounts.creator.to_account_info(),
+                ctx.accounts.metadata.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.sysvar_instructions.to_account_info(),
+                ctx.accounts.token_metadata_program.to_account_info(),
+            ],
+        )?;
 
-    /// CHECK:
-    #[account(mut)]
-    pub payer: AccountInfo<'info>,
+        Ok(())
+    }
 
-    pub rent: Sysvar<'info, Rent>,
+    pub fn burn_nft(ctx: Context<BurnNft>) -> Result<()> {
+        if ctx.accounts.collection_pda.programmable {
+            let token_record = programmable_account(&ctx.accounts.token_record)?;
+            let sysvar_instructions = programmable_account(&ctx.accounts.sysvar_instructions)?;
+
+            // Burn the pNFT, Metaplex also closes its token record
+            let burn_ix = BurnBuilder::new()
+                .authority(ctx.accounts.holder.key())
+                .collection_metadata(ctx.accounts.collection_metadata.key())
+                .metadata(ctx.accounts.metadata.key())
+                .edition(ctx.accounts.master_edition.key())
+                .mint(ctx.accounts.mint.key())
+                .token(ctx.accounts.holder_token_account.key())
+                .token_record(token_record.key())
+                .spl_token_program(ctx.accounts.token_program.key())
+                .build(BurnArgs::V1 { amount: 1 })
+                .map_err(|_| error!(MarketplaceError::MetadataInstructionFailed))?
+                .instruction();
+
+            invoke(
+                &burn_ix,
+                &[
+                    ctx.accounts.holder.to_account_info(),
+                    ctx.accounts.collection_metadata.to_account_info(),
+                    ctx.accounts.metadata.to_account_info(),
+                    ctx.accounts.master_edition.to_account_info(),
+                    ctx.accounts.mint.to_account_info(),
+                    ctx.accounts.holder_token_account.to_account_info(),
+                    token_record,
+                    ctx.accounts.system_program.to_account_info(),
+                    sysvar_instructions,
+                    ctx.accounts.token_program.to_account_info(),
+                    ctx.accounts.token_metadata_program.to_account_info(),
+                ],
+            )?;
+        } else {
+            // Burn the token and close its metadata, master edition and token accounts
+            invoke(
+                &mpl_token_metadata::instruction::burn_nft(
+                    ctx.accounts.token_metadata_program.key(),
+                    ctx.accounts.metadata.key(),
+                    ctx.accounts.holder.key(),
+                    ctx.accounts.mint.key(),
+                    ctx.accounts.holder_token_account.key(),
+                    ctx.accounts.master_edition.key(),
+                    ctx.accounts.token_program.key(),
+                    Some(ctx.accounts.collection_metadata.key()),
+                ),
+                &[
+                    ctx.accounts.metadata.to_account_info(),
+                    ctx.accounts.holder.to_account_info(),
+                    ctx.accounts.mint.to_account_info(),
+                    ctx.accounts.holder_token_account.to_account_info(),
+                    ctx.accounts.master_edition.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                    ctx.accounts.collection_metadata.to_account_info(),
+                    ctx.accounts.token_metadata_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        // nft_pda is closed to the holder by its account constraint, minted_count
+        // keeps counting the burned NFTs so max_supply still caps every mint
+        ctx.accounts.collection_pda.burned_count = ctx.accounts.collection_pda.burned_count
+            .checked_add(1)
+            .ok_or(MarketplaceError::MathOverflow)?;
+
+        Ok(())
+    }
+
+    pub fn print_edition<'info>(
+        ctx: Context<'_, '_, '_, 'info, PrintEdition<'info>>,
+    ) -> Result<()> {
+        let master_edition = MasterEditionV2::from_account_info(&ctx.accounts.master_edition)?;
+        let edition = master_edition.supply
+            .checked_add(1)
+            .ok_or(MarketplaceError::MathOverflow)?;
+
+        let (edition_marker, _) = find_edition_account(
+            &ctx.accounts.master_mint.key(),
+            (edition / EDITION_MARKER_BIT_SIZE).to_string(),
+        );
+        require_keys_eq!(
+            ctx.accounts.edition_marker.key(),
+            edition_marker,
+            MarketplaceError::WrongEditionMarker
+        );
+
+        // Pay the edition price to the creators of the collection by share,
+        // their wallets come in the remaining accounts in the creators order
+        let edition_price = ctx.accounts.nft_pda.edition_price;
+        if edition_price > 0 {
+            let creators = &ctx.accounts.collection_pda.creators;
+            require!(
+                ctx.remaining_accounts.len() >= creators.len(),
+                MarketplaceError::CreatorAccountsMismatch
+            );
+
+            for (creator, creator_account) in creators.iter().zip(ctx.remaining_accounts.iter()) {
+                require_keys_eq!(
+                    creator_account.key(),
+                    creator.address,
+                    MarketplaceError::CreatorAccountsMismatch
+                );
+                if creator.address == ctx.accounts.buyer.key() {
+                    continue;
+                }
+                transfer_lamports(
+                    &ctx.accounts.buyer.to_account_info(),
+                    creator_account,
+                    &ctx.accounts.system_program,
+                    basis_points_of(edition_price, creator.share as u16 * 100)?,
+                )?;
+            }
+        }
+
+        // Create an account to become it in the edition token_mint
+        system_program::create_account(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::CreateAccount {
+                    from: ctx.accounts.buyer.to_account_info(),
+                    to: ctx.accounts.edition_mint.to_account_info(),
+                },
+            ),
+            ctx.accounts.rent.minimum_balance(token::Mint::LEN),
+            token::Mint::LEN as u64,
+            &ctx.accounts.token_program.key(),
+        )?;
+
+        // Create the token_mint for the edition
+        token_interface::initialize_mint(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::InitializeMint {
+                    mint: ctx.accounts.edition_mint.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+            ),
+            0,
+            &ctx.accounts.buyer.key(),
+            Some(&ctx.accounts.buyer.key()),
+        )?;
+
+        // Create ATA for the buyer
+        associated_token::create(CpiContext::new(
+            ctx.accounts.associated_token_program.to_account_info(),
+            associated_token::Create {
+                payer: ctx.accounts.buyer.to_account_info(),
+                associated_token: ctx.accounts.edition_token_account.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+                mint: ctx.accounts.edition_mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+        ))?;
+
+        // Mint the edition token
+        token_interface::mint_to(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::MintTo {
+                    mint: ctx.accounts.edition_mint.to_account_info(),
+                    to: ctx.accounts.edition_token_account.to_account_info(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+
+        if ctx.accounts.collection_pda.programmable {
+            let edition_token_record = programmable_account(&ctx.accounts.edition_token_record)?;
+            let sysvar_instructions = programmable_account(&ctx.accounts.sysvar_instructions)?;
+
+            // Print the pNFT edition, Metaplex freezes the edition token and creates its token record
+            let print_ix = PrintBuilder::new()
+                .edition_metadata(ctx.accounts.edition_metadata.key())
+                .edition(ctx.accounts.edition_edition.key())
+                .edition_mint(ctx.accounts.edition_mint.key())
+                .edition_token_account_owner(ctx.accounts.buyer.key())
+                .edition_token_account(ctx.accounts.edition_token_account.key())
+                .edition_mint_authority(ctx.accounts.buyer.key())
+                .edition_token_record(edition_token_record.key())
+                .master_edition(ctx.accounts.master_edition.key())
+                .edition_marker_pda(ctx.accounts.edition_marker.key())
+                .payer(ctx.accounts.buyer.key())
+                .master_token_account_owner(ctx.accounts.master_holder.key())
+                .master_token_account(ctx.accounts.master_token_account.key())
+                .master_metadata(ctx.accounts.master_metadata.key())
+                .update_authority(ctx.accounts.collection_pda.key())
+                .initialize_mint(false)
+                .build(PrintArgs::V1 { edition })
+                .map_err(|_| error!(MarketplaceError::MetadataInstructionFailed))?
+                .instruction();
+
+            invoke(
+                &print_ix,
+                &[
+                    ctx.accounts.edition_metadata.to_account_info(),
+                    ctx.accounts.edition_edition.to_account_info(),
+                    ctx.accounts.edition_mint.to_account_info(),
+                    ctx.accounts.buyer.to_account_info(),
+                    ctx.accounts.edition_token_account.to_account_info(),
+                    edition_token_record,
+                    ctx.accounts.master_edition.to_account_info(),
+                    ctx.accounts.edition_marker.to_account_info(),
+                    ctx.accounts.master_holder.to_account_info(),
+                    ctx.accounts.master_token_account.to_account_info(),
+                    ctx.accounts.master_metadata.to_account_info(),
+                    ctx.accounts.collection_pda.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                    ctx.accounts.associated_token_program.to_account_info(),
+                    sysvar_instructions,
+                    ctx.accounts.system_program.to_account_info(),
+                    ctx.accounts.token_metadata_program.to_account_info(),
+                ],
+            )?;
+        } else {
+            // Print the edition, Metaplex checks the master edition max supply
+            invoke(
+                &mint_new_edition_from_master_edition_via_token(
+                    ctx.accounts.token_metadata_program.key(),
+                    ctx.accounts.edition_metadata.key(),
+                    ctx.accounts.edition_edition.key(),
+                    ctx.accounts.master_edition.key(),
+                    ctx.accounts.edition_mint.key(),
+                    ctx.accounts.buyer.key(),
+                    ctx.accounts.buyer.key(),
+                    ctx.accounts.master_holder.key(),
+                    ctx.accounts.master_token_account.key(),
+                    ctx.accounts.collection_pda.key(),
+                    ctx.accounts.master_metadata.key(),
+                    ctx.accounts.master_mint.key(),
+                    edition,
+                ),
+                &[
+                    ctx.accounts.edition_metadata.to_account_info(),
+                    ctx.accounts.edition_edition.to_account_info(),
+                    ctx.accounts.master_edition.to_account_info(),
+                    ctx.accounts.edition_mint.to_account_info(),
+                    ctx.accounts.edition_marker.to_account_info(),
+                    ctx.accounts.buyer.to_account_info(),
+                    ctx.accounts.master_holder.to_account_info(),
+                    ctx.accounts.master_token_account.to_account_info(),
+                    ctx.accounts.collection_pda.to_account_info(),
+                    ctx.accounts.master_metadata.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                    ctx.accounts.rent.to_account_info(),
+                    ctx.accounts.token_metadata_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        // Register the edition in the collection with its number
+        let clock: Clock = Clock::get()?;
+
+        ctx.accounts.edition_pda.token_mint = ctx.accounts.edition_mint.key();
+        ctx.accounts.edition_pda.collection_mint = ctx.accounts.nft_pda.collection_mint;
+        ctx.accounts.edition_pda.name = ctx.accounts.nft_pda.name.clone();
+        ctx.accounts.edition_pda.ipfs_image_hash = ctx.accounts.nft_pda.ipfs_image_hash.clone();
+        ctx.accounts.edition_pda.edition = edition;
+        ctx.accounts.edition_pda.edition_price = 0;
+        ctx.accounts.edition_pda.created = clock.unix_timestamp;
+
+        Ok(())
+    }
+
+    pub fn set_edition_price(
+        ctx: Context<SetEditionPrice>,
+        edition_price: u64,
+    ) -> Result<()> {
+        ctx.accounts.nft_pda.edition_price = edition_price;
+
+        Ok(())
+    }
+
+    pub fn list_nft(
+        ctx: Context<ListNft>,
+        price: u64,
+        custody: ListingCustody,
+    ) -> Result<()> {
+        list(ctx, price, ListingKind::FixedPrice, custody)
+    }
+
+    pub fn list_nft_dutch(
+        ctx: Context<ListNft>,
+        start_price: u64,
+        floor_price: u64,
+        start_time: i64,
+        end_time: i64,
+        custody: ListingCustody,
+    ) -> Result<()> {
+        require!(
+            floor_price > 0 && start_price > floor_price && end_time > start_time,
+            MarketplaceError::InvalidDutchAuction
+        );
+
+        list(ctx, start_price, ListingKind::Dutch { floor_price, start_time, end_time }, custody)
+    }
+
+    pub fn delist_nft(ctx: Context<DelistNft>) -> Result<()> {
+        let nft_pda = ctx.accounts.nft_pda.key();
+        let listing_bump = ctx.accounts.listing_pda.bump;
+        let signer_seeds = [
+            b"listing".as_ref(),
+            nft_pda.as_ref(),
+            &[listing_bump],
+        ];
+
+        if ctx.accounts.listing_pda.custody == ListingCustody::SaleDelegate {
+            let metadata = programmable_account(&ctx.accounts.metadata)?;
+            let edition = programmable_account(&ctx.accounts.edition)?;
+            let token_record = programmable_account(&ctx.accounts.owner_token_record)?;
+            let sysvar_instructions = programmable_account(&ctx.accounts.sysvar_instructions)?;
+            let token_metadata_program = programmable_account(&ctx.accounts.token_metadata_program)?;
+
+            // Revoke the sale delegate of listing_pda, Metaplex unlocks the pNFT
+            let mut revoke_builder = RevokeBuilder::new();
+            revoke_builder
+                .delegate(ctx.accounts.listing_pda.key())
+                .metadata(metadata.key())
+                .master_edition(edition.key())
+                .token_record(token_record.key())
+                .mint(ctx.accounts.mint.key())
+                .token(ctx.accounts.seller_token_account.key())
+                .authority(ctx.accounts.seller.key())
+                .payer(ctx.accounts.seller.key())
+                .spl_token_program(ctx.accounts.token_program.key());
+            if let Some(authorization_rules) = &ctx.accounts.authorization_rules {
+                revoke_builder
+                    .authorization_rules_program(programmable_account(&ctx.accounts.authorization_rules_program)?.key())
+                    .authorization_rules(authorization_rules.key());
+            }
+            let revoke_ix = revoke_builder
+                .build(RevokeArgs::SaleV1)
+                .map_err(|_| error!(MarketplaceError::MissingProgrammableAccount))?
+                .instruction();
+
+            let mut revoke_accounts = vec![
+                ctx.accounts.listing_pda.to_account_info(),
+                metadata,
+                edition,
+                token_record,
+                ctx.accounts.mint.to_account_info(),
+                ctx.accounts.seller_token_account.to_account_info(),
+                ctx.accounts.seller.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions,
+                ctx.accounts.token_program.to_account_info(),
+                token_metadata_program,
+            ];
+            if let Some(authorization_rules) = &ctx.accounts.authorization_rules {
+                revoke_accounts.push(programmable_account(&ctx.accounts.authorization_rules_program)?);
+                revoke_accounts.push(authorization_rules.to_account_info());
+            }
+
+            invoke(&revoke_ix, &revoke_accounts)?;
+
+            return Ok(());
+        }
+
+        if ctx.accounts.listing_pda.custody == ListingCustody::Frozen {
+            thaw_listed_nft(
+                &ctx.accounts.listing_pda,
+                &ctx.accounts.seller_token_account.to_account_info(),
+                &programmable_account(&ctx.accounts.edition)?,
+                &ctx.accounts.mint,
+                &ctx.accounts.token_program,
+                &programmable_account(&ctx.accounts.token_metadata_program)?,
+                &signer_seeds,
+            )?;
+
+            // Remove listing_pda as delegate, listing_pda is closed by its account constraint
+            token_interface::revoke(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::Revoke {
+                        source: ctx.accounts.seller_token_account.to_account_info(),
+                        authority: ctx.accounts.seller.to_account_info(),
+                    },
+                ),
+            )?;
+
+            return Ok(());
+        }
+
+        let escrow_token_account = ctx.accounts.escrow_token_account
+            .as_ref()
+            .ok_or(MarketplaceError::MissingEscrowAccount)?;
+
+        // Return the NFT from the escrow to the seller
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::TransferChecked {
+                    from: escrow_token_account.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.seller_token_account.to_account_info(),
+                    authority: ctx.accounts.listing_pda.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+            1,
+            0,
+        )?;
+
+        // Close the escrow, listing_pda is closed by its account constraint
+        token_interface::close_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::CloseAccount {
+                    account: escrow_token_account.to_account_info(),
+                    destination: ctx.accounts.seller.to_account_info(),
+                    authority: ctx.accounts.listing_pda.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn buy_nft<'info>(
+        ctx: Context<'_, '_, '_, 'info, BuyNft<'info>>,
+        max_price: u64,
+        seller: Pubkey,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, MarketplaceError::MarketplacePaused);
+
+        // Fail if the listing changed after the buyer signed the transaction
+        let clock: Clock = Clock::get()?;
+        let price = ctx.accounts.listing_pda.current_price(clock.unix_timestamp);
+        require!(price <= max_price, MarketplaceError::PriceAboveMaximum);
+        require_keys_eq!(ctx.accounts.listing_pda.seller, seller, MarketplaceError::SellerMismatch);
+
+        // Pay with lamports from the buyer or with tokens from its payment account
+        let payment_mint = ctx.accounts.listing_pda.payment_mint;
+        let (from, seller_account, treasury_account) = match payment_mint {
+            None => (
+                ctx.accounts.buyer.to_account_info(),
+                ctx.accounts.seller.to_account_info(),
+                ctx.accounts.treasury.to_account_info(),
+            ),
+            Some(_) => (
+                payment_account(&ctx.accounts.buyer_payment_account)?,
+                payment_account(&ctx.accounts.seller_payment_account)?,
+                payment_account(&ctx.accounts.treasury_payment_account)?,
+            ),
+        };
+        let payment = Payment {
+            payment_mint: payment_mint_account(payment_mint, &ctx.accounts.payment_mint)?,
+            from,
+            authority: ctx.accounts.buyer.to_account_info(),
+            signer_seeds: &[],
+            system_program: &ctx.accounts.system_program,
+            token_program: &ctx.accounts.token_program,
+        };
+
+        // Pay the marketplace fee, the royalties and the rest to the seller
+        settle_sale(
+            &payment,
+            &ctx.accounts.marketplace,
+            &seller_account,
+            &seller,
+            &treasury_account,
+            &ctx.accounts.treasury.key(),
+            &ctx.accounts.metadata,
+            ctx.remaining_accounts,
+            price,
+        )?;
+
+        let nft_pda = ctx.accounts.nft_pda.key();
+        let listing_bump = ctx.accounts.listing_pda.bump;
+        let signer_seeds = [
+            b"listing".as_ref(),
+            nft_pda.as_ref(),
+            &[listing_bump],
+        ];
+
+        if ctx.accounts.listing_pda.custody == ListingCustody::SaleDelegate {
+            // Transfer the pNFT from the seller to the buyer, signed by listing_pda as sale delegate
+            ProgrammableTransfer {
+                token: programmable_account(&ctx.accounts.seller_token_account)?,
+                token_owner: ctx.accounts.seller.to_account_info(),
+                destination: ctx.accounts.buyer_token_account.to_account_info(),
+                destination_owner: ctx.accounts.buyer.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                metadata: ctx.accounts.metadata.to_account_info(),
+                edition: programmable_account(&ctx.accounts.edition)?,
+                owner_token_record: programmable_account(&ctx.accounts.owner_token_record)?,
+                destination_token_record: programmable_account(&ctx.accounts.destination_token_record)?,
+                authority: ctx.accounts.listing_pda.to_account_info(),
+                payer: ctx.accounts.buyer.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions: programmable_account(&ctx.accounts.sysvar_instructions)?,
+                token_program: ctx.accounts.token_program.to_account_info(),
+                associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+                token_metadata_program: programmable_account(&ctx.accounts.token_metadata_program)?,
+                authorization_rules: authorization_rules_accounts(
+                    &ctx.accounts.authorization_rules_program,
+                    &ctx.accounts.authorization_rules,
+                )?,
+            }
+            .invoke(&[&signer_seeds])?;
+
+            return Ok(());
+        }
+
+        if ctx.accounts.listing_pda.custody == ListingCustody::Frozen {
+            let seller_token_account = programmable_account(&ctx.accounts.seller_token_account)?;
+
+            thaw_listed_nft(
+                &ctx.accounts.listing_pda,
+                &seller_token_account,
+                &programmable_account(&ctx.accounts.edition)?,
+                &ctx.accounts.mint,
+                &ctx.accounts.token_program,
+                &programmable_account(&ctx.accounts.token_metadata_program)?,
+                &signer_seeds,
+            )?;
+
+            // Move the NFT from the seller to the buyer, signed by listing_pda as delegate
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: seller_token_account,
+                        mint: ctx.accounts.mint.to_account_info(),
+                        to: ctx.accounts.buyer_token_account.to_account_info(),
+                        authority: ctx.accounts.listing_pda.to_account_info(),
+                    },
+                    &[&signer_seeds],
+                ),
+                1,
+                0,
+            )?;
+
+            return Ok(());
+        }
+
+        let escrow_token_account = ctx.accounts.escrow_token_account
+            .as_ref()
+            .ok_or(MarketplaceError::MissingEscrowAccount)?;
+
+        // Move the NFT from the escrow to the buyer
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::TransferChecked {
+                    from: escrow_token_account.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.buyer_token_account.to_account_info(),
+                    authority: ctx.accounts.listing_pda.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+            1,
+            0,
+        )?;
+
+        // Close the escrow, listing_pda is closed by its account constraint
+        token_interface::close_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::CloseAccount {
+                    account: escrow_token_account.to_account_info(),
+                    destination: ctx.accounts.seller.to_account_info(),
+                    authority: ctx.accounts.listing_pda.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn initialize_marketplace(
+        ctx: Context<InitializeMarketplace>,
+        fee_basis_points: u16,
+    ) -> Result<()> {
+        require!(fee_basis_points <= MAX_BASIS_POINTS, MarketplaceError::InvalidBasisPoints);
+
+        ctx.accounts.marketplace.admin = ctx.accounts.admin.key();
+        ctx.accounts.marketplace.fee_basis_points = fee_basis_points;
+        ctx.accounts.marketplace.treasury_bump = *ctx.bumps.get("treasury").ok_or(MarketplaceError::MissingBump)?;
+        ctx.accounts.marketplace.paused = false;
+        ctx.accounts.marketplace.bump = *ctx.bumps.get("marketplace").ok_or(MarketplaceError::MissingBump)?;
+
+        ctx.accounts.treasury.bump = *ctx.bumps.get("treasury").ok_or(MarketplaceError::MissingBump)?;
+
+        Ok(())
+    }
+
+    pub fn update_marketplace(
+        ctx: Context<UpdateMarketplace>,
+        admin: Option<Pubkey>,
+        fee_basis_points: Option<u16>,
+        paused: Option<bool>,
+        payment_mints: Option<Vec<Pubkey>>,
+    ) -> Result<()> {
+        if let Some(admin) = admin {
+            ctx.accounts.marketplace.admin = admin;
+        }
+        if let Some(fee_basis_points) = fee_basis_points {
+            require!(fee_basis_points <= MAX_BASIS_POINTS, MarketplaceError::InvalidBasisPoints);
+            ctx.accounts.marketplace.fee_basis_points = fee_basis_points;
+        }
+        if let Some(paused) = paused {
+            ctx.accounts.marketplace.paused = paused;
+        }
+        if let Some(payment_mints) = payment_mints {
+            require!(payment_mints.len() <= MAX_PAYMENT_MINTS, MarketplaceError::TooManyPaymentMints);
+            ctx.accounts.marketplace.payment_mints = payment_mints;
+        }
+
+        Ok(())
+    }
+
+    pub fn withdraw_treasury(
+        ctx: Context<WithdrawTreasury>,
+        amount: u64,
+    ) -> Result<()> {
+        // The treasury must stay rent exempt
+        let treasury = ctx.accounts.treasury.to_account_info();
+        let rent = Rent::get()?.minimum_balance(treasury.data_len());
+        require!(
+            treasury.lamports().saturating_sub(rent) >= amount,
+            MarketplaceError::InsufficientFunds
+        );
+
+        **treasury.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.destination.try_borrow_mut_lamports()? += amount;
+
+        Ok(())
+    }
+
+    pub fn withdraw_treasury_tokens(
+        ctx: Context<WithdrawTreasuryTokens>,
+        amount: u64,
+    ) -> Result<()> {
+        let treasury_bump = ctx.accounts.marketplace.treasury_bump;
+        let signer_seeds = [
+            b"treasury".as_ref(),
+            &[treasury_bump],
+        ];
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::TransferChecked {
+                    from: ctx.accounts.treasury_payment_account.to_account_info(),
+                    mint: ctx.accounts.payment_mint.to_account_info(),
+                    to: ctx.accounts.destination.to_account_info(),
+                    authority: ctx.accounts.treasury.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+            amount,
+            ctx.accounts.payment_mint.decimals,
+        )
+    }
+
+    pub fn make_offer(
+        ctx: Context<MakeOffer>,
+        price: u64,
+        expiry: i64,
+    ) -> Result<()> {
+        let clock: Clock = Clock::get()?;
+
+        require!(!ctx.accounts.marketplace.paused, MarketplaceError::MarketplacePaused);
+        require!(price > 0, MarketplaceError::InvalidPrice);
+        require!(expiry > clock.unix_timestamp, MarketplaceError::InvalidExpiry);
+
+        let payment_mint = ctx.accounts.payment_mint.as_ref().map(|m| m.key());
+        assert_payment_mint(&ctx.accounts.marketplace, &payment_mint)?;
+
+        // Escrow the offered lamports in offer_pda, or the offered tokens in offer_escrow
+        let (from, escrow) = match payment_mint {
+            None => (
+                ctx.accounts.bidder.to_account_info(),
+                ctx.accounts.offer_pda.to_account_info(),
+            ),
+            Some(_) => (
+                payment_account(&ctx.accounts.bidder_payment_account)?,
+                payment_account(&ctx.accounts.offer_escrow)?,
+            ),
+        };
+        let payment = Payment {
+            payment_mint: payment_mint_account(payment_mint, &ctx.accounts.payment_mint)?,
+            from,
+            authority: ctx.accounts.bidder.to_account_info(),
+            signer_seeds: &[],
+            system_program: &ctx.accounts.system_program,
+            token_program: &ctx.accounts.token_program,
+        };
+        payment.pay(&escrow, &ctx.accounts.offer_pda.key(), price)?;
+
+        ctx.accounts.offer_pda.bidder = ctx.accounts.bidder.key();
+        ctx.accounts.offer_pda.token_mint = ctx.accounts.mint.key();
+        ctx.accounts.offer_pda.price = price;
+        ctx.accounts.offer_pda.expiry = expiry;
+        ctx.accounts.offer_pda.payment_mint = payment_mint;
+        ctx.accounts.offer_pda.bump = *ctx.bumps.get("offer_pda").ok_or(MarketplaceError::MissingBump)?;
+        ctx.accounts.offer_pda.created = clock.unix_timestamp;
+
+        Ok(())
+    }
+
+    pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+        // Anyone can refund the bidder once the offer expired
+        if ctx.accounts.authority.key() != ctx.accounts.bidder.key() {
+            let clock: Clock = Clock::get()?;
+            require!(
+                clock.unix_timestamp >= ctx.accounts.offer_pda.expiry,
+                MarketplaceError::OfferNotExpired
+            );
+        }
+
+        // Refund escrowed tokens, escrowed lamports and rent go back to the bidder when offer_pda is closed
+        if let Some(payment_mint) = ctx.accounts.offer_pda.payment_mint {
+            let nft_pda = ctx.accounts.nft_pda.key();
+            let bidder = ctx.accounts.bidder.key();
+            let offer_bump = ctx.accounts.offer_pda.bump;
+            let signer_seeds = [
+                b"offer".as_ref(),
+                nft_pda.as_ref(),
+                bidder.as_ref(),
+                &[offer_bump],
+            ];
+            let offer_escrow = payment_account(&ctx.accounts.offer_escrow)?;
+
+            let payment = Payment {
+                payment_mint: payment_mint_account(Some(payment_mint), &ctx.accounts.payment_mint)?,
+                from: offer_escrow.clone(),
+                authority: ctx.accounts.offer_pda.to_account_info(),
+                signer_seeds: &[&signer_seeds],
+                system_program: &ctx.accounts.system_program,
+                token_program: &ctx.accounts.token_program,
+            };
+            payment.pay(
+                &payment_account(&ctx.accounts.bidder_payment_account)?,
+                &bidder,
+                ctx.accounts.offer_pda.price,
+            )?;
+
+            token_interface::close_account(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::CloseAccount {
+                        account: offer_escrow,
+                        destination: ctx.accounts.bidder.to_account_info(),
+                        authority: ctx.accounts.offer_pda.to_account_info(),
+                    },
+                    &[&signer_seeds],
+                ),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn accept_offer<'info>(
+        ctx: Context<'_, '_, '_, 'info, AcceptOffer<'info>>,
+        price: u64,
+    ) -> Result<()> {
+        let clock: Clock = Clock::get()?;
+
+        require!(!ctx.accounts.marketplace.paused, MarketplaceError::MarketplacePaused);
+        require!(ctx.accounts.offer_pda.price == price, MarketplaceError::PriceMismatch);
+        require!(clock.unix_timestamp < ctx.accounts.offer_pda.expiry, MarketplaceError::OfferExpired);
+
+        if ctx.accounts.collection_pda.programmable {
+            // Transfer the pNFT from the seller to the bidder, Metaplex moves its token record along
+            ProgrammableTransfer {
+                token: ctx.accounts.seller_token_account.to_account_info(),
+                token_owner: ctx.accounts.seller.to_account_info(),
+                destination: ctx.accounts.bidder_token_account.to_account_info(),
+                destination_owner: ctx.accounts.bidder.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                metadata: ctx.accounts.metadata.to_account_info(),
+                edition: programmable_account(&ctx.accounts.edition)?,
+                owner_token_record: programmable_account(&ctx.accounts.owner_token_record)?,
+                destination_token_record: programmable_account(&ctx.accounts.destination_token_record)?,
+                authority: ctx.accounts.seller.to_account_info(),
+                payer: ctx.accounts.seller.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions: programmable_account(&ctx.accounts.sysvar_instructions)?,
+                token_program: ctx.accounts.token_program.to_account_info(),
+                associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+                token_metadata_program: programmable_account(&ctx.accounts.token_metadata_program)?,
+                authorization_rules: authorization_rules_accounts(
+                    &ctx.accounts.authorization_rules_program,
+                    &ctx.accounts.authorization_rules,
+                )?,
+            }
+            .invoke(&[])?;
+        } else {
+            // Move the NFT from the seller to the bidder
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: ctx.accounts.seller_token_account.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        to: ctx.accounts.bidder_token_account.to_account_info(),
+                        authority: ctx.accounts.seller.to_account_info(),
+                    },
+                ),
+                1,
+                0,
+            )?;
+        }
+
+        let nft_pda = ctx.accounts.nft_pda.key();
+        let bidder = ctx.accounts.bidder.key();
+        let offer_bump = ctx.accounts.offer_pda.bump;
+        let signer_seeds = [
+            b"offer".as_ref(),
+            nft_pda.as_ref(),
+            bidder.as_ref(),
+            &[offer_bump],
+        ];
+
+        // Pay the sale out of the escrowed lamports or tokens
+        let payment_mint = ctx.accounts.offer_pda.payment_mint;
+        let (from, seller_account, treasury_account) = match payment_mint {
+            None => (
+                ctx.accounts.offer_pda.to_account_info(),
+                ctx.accounts.seller.to_account_info(),
+                ctx.accounts.treasury.to_account_info(),
+            ),
+            Some(_) => (
+                payment_account(&ctx.accounts.offer_escrow)?,
+                payment_account(&ctx.accounts.seller_payment_account)?,
+                payment_account(&ctx.accounts.treasury_payment_account)?,
+            ),
+        };
+        let payment = Payment {
+            payment_mint: payment_mint_account(payment_mint, &ctx.accounts.payment_mint)?,
+            from,
+            authority: ctx.accounts.offer_pda.to_account_info(),
+            signer_seeds: &[&signer_seeds],
+            system_program: &ctx.accounts.system_program,
+            token_program: &ctx.accounts.token_program,
+        };
+
+        settle_sale(
+            &payment,
+            &ctx.accounts.marketplace,
+            &seller_account,
+            &ctx.accounts.seller.key(),
+            &treasury_account,
+            &ctx.accounts.treasury.key(),
+            &ctx.accounts.metadata,
+            ctx.remaining_accounts,
+            price,
+        )?;
+
+        // Close the emptied token escrow, offer_pda is closed by its account constraint
+        if payment_mint.is_some() {
+            token_interface::close_account(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::CloseAccount {
+                        account: payment.from.clone(),
+                        destination: ctx.accounts.bidder.to_account_info(),
+                        authority: ctx.accounts.offer_pda.to_account_info(),
+                    },
+                    &[&signer_seeds],
+                ),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn make_collection_bid(
+        ctx: Context<MakeCollectionBid>,
+        price: u64,
+        quantity: u32,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, MarketplaceError::MarketplacePaused);
+        require!(price > 0, MarketplaceError::InvalidPrice);
+        require!(quantity > 0, MarketplaceError::InvalidQuantity);
+
+        // Escrow the lamports for every NFT of the bid in collection_bid_pda
+        let total = price
+            .checked_mul(quantity as u64)
+            .ok_or(MarketplaceError::MathOverflow)?;
+
+        transfer_lamports(
+            &ctx.accounts.bidder.to_account_info(),
+            &ctx.accounts.collection_bid_pda.to_account_info(),
+            &ctx.accounts.system_program,
+            total,
+        )?;
+
+        let clock: Clock = Clock::get()?;
+
+        ctx.accounts.collection_bid_pda.bidder = ctx.accounts.bidder.key();
+        ctx.accounts.collection_bid_pda.collection_mint = ctx.accounts.collection_pda.token_mint;
+        ctx.accounts.collection_bid_pda.price = price;
+        ctx.accounts.collection_bid_pda.quantity = quantity;
+        ctx.accounts.collection_bid_pda.bump = *ctx.bumps.get("collection_bid_pda").ok_or(MarketplaceError::MissingBump)?;
+        ctx.accounts.collection_bid_pda.created = clock.unix_timestamp;
+
+        Ok(())
+    }
+
+    pub fn cancel_collection_bid(_ctx: Context<CancelCollectionBid>) -> Result<()> {
+        // Escrowed lamports and rent go back to the bidder when collection_bid_pda is closed
+        Ok(())
+    }
+
+    pub fn fill_collection_bid<'info>(
+        ctx: Context<'_, '_, '_, 'info, FillCollectionBid<'info>>,
+        price: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.marketplace.paused, MarketplaceError::MarketplacePaused);
+        require!(ctx.accounts.collection_bid_pda.price == price, MarketplaceError::PriceMismatch);
+
+        // The NFT must be verified as part of the collection in Metaplex too
+        assert_verified_collection(
+            &ctx.accounts.metadata,
+            &ctx.accounts.collection_bid_pda.collection_mint,
+        )?;
+
+        if ctx.accounts.collection_pda.programmable {
+            // Transfer the pNFT from the seller to the bidder, Metaplex moves its token record along
+            ProgrammableTransfer {
+                token: ctx.accounts.seller_token_account.to_account_info(),
+                token_owner: ctx.accounts.seller.to_account_info(),
+                destination: ctx.accounts.bidder_token_account.to_account_info(),
+                destination_owner: ctx.accounts.bidder.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                metadata: ctx.accounts.metadata.to_account_info(),
+                edition: programmable_account(&ctx.accounts.edition)?,
+                owner_token_record: programmable_account(&ctx.accounts.owner_token_record)?,
+                destination_token_record: programmable_account(&ctx.accounts.destination_token_record)?,
+                authority: ctx.accounts.seller.to_account_info(),
+                payer: ctx.accounts.seller.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions: programmable_account(&ctx.accounts.sysvar_instructions)?,
+                token_program: ctx.accounts.token_program.to_account_info(),
+                associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+                token_metadata_program: programmable_account(&ctx.accounts.token_metadata_program)?,
+                authorization_rules: authorization_rules_accounts(
+                    &ctx.accounts.authorization_rules_program,
+                    &ctx.accounts.authorization_rules,
+                )?,
+            }
+            .invoke(&[])?;
+        } else {
+            // Move the NFT from the seller to the bidder
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: ctx.accounts.seller_token_account.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        to: ctx.accounts.bidder_token_account.to_account_info(),
+                        authority: ctx.accounts.seller.to_account_info(),
+                    },
+                ),
+                1,
+                0,
+            )?;
+        }
+
+        // Pay the sale out of the escrowed lamports
+        let payment = Payment {
+            payment_mint: None,
+            from: ctx.accounts.collection_bid_pda.to_account_info(),
+            authority: ctx.accounts.collection_bid_pda.to_account_info(),
+            signer_seeds: &[],
+            system_program: &ctx.accounts.system_program,
+            token_program: &ctx.accounts.token_program,
+        };
+
+        settle_sale(
+            &payment,
+            &ctx.accounts.marketplace,
+            &ctx.accounts.seller.to_account_info(),
+            &ctx.accounts.seller.key(),
+            &ctx.accounts.treasury.to_account_info(),
+            &ctx.accounts.treasury.key(),
+            &ctx.accounts.metadata,
+            ctx.remaining_accounts,
+            price,
+        )?;
+
+        // Close the bid once it is completely filled
+        ctx.accounts.collection_bid_pda.quantity -= 1;
+        if ctx.accounts.collection_bid_pda.quantity == 0 {
+            ctx.accounts.collection_bid_pda.close(ctx.accounts.bidder.to_account_info())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn create_auction(
+        ctx: Context<CreateAuction>,
+        reserve_price: u64,
+        min_increment: u64,
+        end_time: i64,
+        extension_seconds: i64,
+    ) -> Result<()> {
+        let clock: Clock = Clock::get()?;
+
+        require!(!ctx.accounts.marketplace.paused, MarketplaceError::MarketplacePaused);
+        require!(reserve_price > 0, MarketplaceError::InvalidPrice);
+        require!(end_time > clock.unix_timestamp, MarketplaceError::InvalidExpiry);
+        require!(
+            (0..=MAX_AUCTION_EXTENSION).contains(&extension_seconds),
+            MarketplaceError::InvalidAuctionExtension
+        );
+
+        let payment_mint = ctx.accounts.payment_mint.as_ref().map(|m| m.key());
+        assert_payment_mint(&ctx.accounts.marketplace, &payment_mint)?;
+
+        if ctx.accounts.collection_pda.programmable {
+            // Transfer the pNFT from the seller into the escrow owned by auction_pda, Metaplex freezes it there
+            ProgrammableTransfer {
+                token: ctx.accounts.seller_token_account.to_account_info(),
+                token_owner: ctx.accounts.seller.to_account_info(),
+                destination: ctx.accounts.escrow_token_account.to_account_info(),
+                destination_owner: ctx.accounts.auction_pda.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                metadata: programmable_account(&ctx.accounts.metadata)?,
+                edition: programmable_account(&ctx.accounts.edition)?,
+                owner_token_record: programmable_account(&ctx.accounts.owner_token_record)?,
+                destination_token_record: programmable_account(&ctx.accounts.destination_token_record)?,
+                authority: ctx.accounts.seller.to_account_info(),
+                payer: ctx.accounts.seller.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions: programmable_account(&ctx.accounts.sysvar_instructions)?,
+                token_program: ctx.accounts.token_program.to_account_info(),
+                associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+                token_metadata_program: programmable_account(&ctx.accounts.token_metadata_program)?,
+                authorization_rules: authorization_rules_accounts(
+                    &ctx.accounts.authorization_rules_program,
+                    &ctx.accounts.authorization_rules,
+                )?,
+            }
+            .invoke(&[])?;
+        } else {
+            // Move the NFT from the seller into the escrow owned by auction_pda
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: ctx.accounts.seller_token_account.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        to: ctx.accounts.escrow_token_account.to_account_info(),
+                        authority: ctx.accounts.seller.to_account_info(),
+                    },
+                ),
+                1,
+                0,
+            )?;
+        }
+
+        ctx.accounts.auction_pda.seller = ctx.accounts.seller.key();
+        ctx.accounts.auction_pda.token_mint = ctx.accounts.mint.key();
+        ctx.accounts.auction_pda.reserve_price = reserve_price;
+        ctx.accounts.auction_pda.min_increment = min_increment;
+        ctx.accounts.auction_pda.end_time = end_time;
+        ctx.accounts.auction_pda.extension_seconds = extension_seconds;
+        ctx.accounts.auction_pda.highest_bidder = Pubkey::default();
+        ctx.accounts.auction_pda.highest_bid = 0;
+        ctx.accounts.auction_pda.payment_mint = payment_mint;
+        ctx.accounts.auction_pda.bump = *ctx.bumps.get("auction_pda").ok_or(MarketplaceError::MissingBump)?;
+        ctx.accounts.auction_pda.escrow_bump = *ctx.bumps.get("escrow_token_account").ok_or(MarketplaceError::MissingBump)?;
+        ctx.accounts.auction_pda.created = clock.unix_timestamp;
+
+        Ok(())
+    }
+
+    pub fn place_bid(
+        ctx: Context<PlaceBid>,
+        amount: u64,
+    ) -> Result<()> {
+        let clock: Clock = Clock::get()?;
+        let auction = &ctx.accounts.auction_pda;
+
+        require!(!ctx.accounts.marketplace.paused, MarketplaceError::MarketplacePaused);
+        require!(clock.unix_timestamp < auction.end_time, MarketplaceError::AuctionEnded);
+
+        let min_bid = if auction.highest_bid == 0 {
+            auction.reserve_price
+        } else {
+            auction.highest_bid
+                .checked_add(auction.min_increment)
+                .ok_or(MarketplaceError::MathOverflow)?
+        };
+        require!(amount >= min_bid, MarketplaceError::BidTooLow);
+
+        // Escrow the new bid in auction_pda, or in bid_escrow for SPL token auctions
+        let payment_mint = ctx.accounts.auction_pda.payment_mint;
+        let (from, escrow) = match payment_mint {
+            None => (
+                ctx.accounts.bidder.to_account_info(),
+                ctx.accounts.auction_pda.to_account_info(),
+            ),
+            Some(_) => (
+                payment_account(&ctx.accounts.bidder_payment_account)?,
+                payment_account(&ctx.accounts.bid_escrow)?,
+            ),
+        };
+        let payment = Payment {
+            payment_mint: payment_mint_account(payment_mint, &ctx.accounts.payment_mint)?,
+            from,
+            authority: ctx.accounts.bidder.to_account_info(),
+            signer_seeds: &[],
+            system_program: &ctx.accounts.system_program,
+            token_program: &ctx.accounts.token_program,
+        };
+        payment.pay(&escrow, &ctx.accounts.auction_pda.key(), amount)?;
+
+        // Refund the outbid bidder
+        if ctx.accounts.auction_pda.highest_bid > 0 {
+            require_keys_eq!(
+                ctx.accounts.previous_bidder.key(),
+                ctx.accounts.auction_pda.highest_bidder,
+                MarketplaceError::WrongBidder
+            );
+
+            let nft_pda = ctx.accounts.nft_pda.key();
+            let auction_bump = ctx.accounts.auction_pda.bump;
+            let signer_seeds = [
+                b"auction".as_ref(),
+                nft_pda.as_ref(),
+                &[auction_bump],
+            ];
+            let previous_bidder_account = match payment_mint {
+                None => ctx.accounts.previous_bidder.to_account_info(),
+                Some(_) => payment_account(&ctx.accounts.previous_bidder_payment_account)?,
+            };
+            let refund = Payment {
+                payment_mint: payment_mint_account(payment_mint, &ctx.accounts.payment_mint)?,
+                from: escrow,
+                authority: ctx.accounts.auction_pda.to_account_info(),
+                signer_seeds: &[&signer_seeds],
+                system_program: &ctx.accounts.system_program,
+                token_program: &ctx.accounts.token_program,
+            };
+            refund.pay(
+                &previous_bidder_account,
+                &ctx.accounts.previous_bidder.key(),
+                ctx.accounts.auction_pda.highest_bid,
+            )?;
+        }
+
+        let auction = &mut ctx.accounts.auction_pda;
+        auction.highest_bidder = ctx.accounts.bidder.key();
+        auction.highest_bid = amount;
+
+        // Extend the auction when the bid arrives in its last seconds
+        if auction.end_time - clock.unix_timestamp < auction.extension_seconds {
+            auction.end_time = clock.unix_timestamp
+                .checked_add(auction.extension_seconds)
+                .ok_or(MarketplaceError::MathOverflow)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn settle_auction<'info>(ctx: Context<'_, '_, '_, 'info, SettleAuction<'info>>) -> Result<()> {
+        let clock: Clock = Clock::get()?;
+        let auction = &ctx.accounts.auction_pda;
+
+        require!(clock.unix_timestamp >= auction.end_time, MarketplaceError::AuctionNotEnded);
+        require!(
+            auction.payment_mint.is_none() || ctx.accounts.bid_escrow.is_some(),
+            MarketplaceError::MissingBidEscrow
+        );
+
+        // Without bids the NFT goes back to the seller
+        let winner = if auction.highest_bid > 0 { auction.highest_bidder } else { auction.seller };
+        require_keys_eq!(ctx.accounts.winner.key(), winner, MarketplaceError::WrongBidder);
+
+        let nft_pda = ctx.accounts.nft_pda.key();
+        let auction_bump = ctx.accounts.auction_pda.bump;
+        let signer_seeds = [
+            b"auction".as_ref(),
+            nft_pda.as_ref(),
+            &[auction_bump],
+        ];
+
+        if auction.highest_bid > 0 {
+            let payment_mint = auction.payment_mint;
+            let (from, seller_account, treasury_account) = match payment_mint {
+                None => (
+                    ctx.accounts.auction_pda.to_account_info(),
+                    ctx.accounts.seller.to_account_info(),
+                    ctx.accounts.treasury.to_account_info(),
+                ),
+                Some(_) => (
+                    payment_account(&ctx.accounts.bid_escrow)?,
+                    payment_account(&ctx.accounts.seller_payment_account)?,
+                    payment_account(&ctx.accounts.treasury_payment_account)?,
+                ),
+            };
+            let payment = Payment {
+                payment_mint: payment_mint_account(payment_mint, &ctx.accounts.payment_mint)?,
+                from,
+                authority: ctx.accounts.auction_pda.to_account_info(),
+                signer_seeds: &[&signer_seeds],
+                system_program: &ctx.accounts.system_program,
+                token_program: &ctx.accounts.token_program,
+            };
+
+            settle_sale(
+                &payment,
+                &ctx.accounts.marketplace,
+                &seller_account,
+                &ctx.accounts.seller.key(),
+                &treasury_account,
+                &ctx.accounts.treasury.key(),
+                &ctx.accounts.metadata,
+                ctx.remaining_accounts,
+                auction.highest_bid,
+            )?;
+        }
+
+        if ctx.accounts.collection_pda.programmable {
+            // Transfer the pNFT from the escrow to the winner, Metaplex leaves the escrow thawed to be closed
+            ProgrammableTransfer {
+                token: ctx.accounts.escrow_token_account.to_account_info(),
+                token_owner: ctx.accounts.auction_pda.to_account_info(),
+                destination: ctx.accounts.winner_token_account.to_account_info(),
+                destination_owner: ctx.accounts.winner.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                metadata: ctx.accounts.metadata.to_account_info(),
+                edition: programmable_account(&ctx.accounts.edition)?,
+                owner_token_record: programmable_account(&ctx.accounts.owner_token_record)?,
+                destination_token_record: programmable_account(&ctx.accounts.destination_token_record)?,
+                authority: ctx.accounts.auction_pda.to_account_info(),
+                payer: ctx.accounts.payer.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions: programmable_account(&ctx.accounts.sysvar_instructions)?,
+                token_program: ctx.accounts.token_program.to_account_info(),
+                associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+                token_metadata_program: programmable_account(&ctx.accounts.token_metadata_program)?,
+                authorization_rules: authorization_rules_accounts(
+                    &ctx.accounts.authorization_rules_program,
+                    &ctx.accounts.authorization_rules,
+                )?,
+            }
+            .invoke(&[&signer_seeds])?;
+        } else {
+            // Move the NFT from the escrow to the winner
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: ctx.accounts.escrow_token_account.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        to: ctx.accounts.winner_token_account.to_account_info(),
+                        authority: ctx.accounts.auction_pda.to_account_info(),
+                    },
+                    &[&signer_seeds],
+                ),
+                1,
+                0,
+            )?;
+        }
+
+        // Close the escrow, auction_pda is closed by its account constraint
+        token_interface::close_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::CloseAccount {
+                    account: ctx.accounts.escrow_token_account.to_account_info(),
+                    destination: ctx.accounts.seller.to_account_info(),
+                    authority: ctx.accounts.auction_pda.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+        )?;
+
+
+        // Close the emptied bid escrow of SPL token auctions
+        if let Some(bid_escrow) = &ctx.accounts.bid_escrow {
+            token_interface::close_account(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::CloseAccount {
+                        account: bid_escrow.to_account_info(),
+                        destination: ctx.accounts.seller.to_account_info(),
+                        authority: ctx.accounts.auction_pda.to_account_info(),
+                    },
+                    &[&signer_seeds],
+                ),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn cancel_auction(ctx: Context<CancelAuction>) -> Result<()> {
+        require!(ctx.accounts.auction_pda.highest_bid == 0, MarketplaceError::AuctionHasBids);
+        require!(
+            ctx.accounts.auction_pda.payment_mint.is_none() || ctx.accounts.bid_escrow.is_some(),
+            MarketplaceError::MissingBidEscrow
+        );
+
+        let nft_pda = ctx.accounts.nft_pda.key();
+        let auction_bump = ctx.accounts.auction_pda.bump;
+        let signer_seeds = [
+            b"auction".as_ref(),
+            nft_pda.as_ref(),
+            &[auction_bump],
+        ];
+
+        if ctx.accounts.collection_pda.programmable {
+            // Return the pNFT from the escrow to the seller, Metaplex leaves the escrow thawed to be closed
+            ProgrammableTransfer {
+                token: ctx.accounts.escrow_token_account.to_account_info(),
+                token_owner: ctx.accounts.auction_pda.to_account_info(),
+                destination: ctx.accounts.seller_token_account.to_account_info(),
+                destination_owner: ctx.accounts.seller.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                metadata: programmable_account(&ctx.accounts.metadata)?,
+                edition: programmable_account(&ctx.accounts.edition)?,
+                owner_token_record: programmable_account(&ctx.accounts.owner_token_record)?,
+                destination_token_record: programmable_account(&ctx.accounts.destination_token_record)?,
+                authority: ctx.accounts.auction_pda.to_account_info(),
+                payer: ctx.accounts.seller.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                sysvar_instructions: programmable_account(&ctx.accounts.sysvar_instructions)?,
+                token_program: ctx.accounts.token_program.to_account_info(),
+                associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+                token_metadata_program: programmable_account(&ctx.accounts.token_metadata_program)?,
+                authorization_rules: authorization_rules_accounts(
+                    &ctx.accounts.authorization_rules_program,
+                    &ctx.accounts.authorization_rules,
+                )?,
+            }
+            .invoke(&[&signer_seeds])?;
+        } else {
+            // Return the NFT from the escrow to the seller
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: ctx.accounts.escrow_token_account.to_account_info(),
+                        mint: ctx.accounts.mint.to_account_info(),
+                        to: ctx.accounts.seller_token_account.to_account_info(),
+                        authority: ctx.accounts.auction_pda.to_account_info(),
+                    },
+                    &[&signer_seeds],
+                ),
+                1,
+                0,
+            )?;
+        }
+
+        // Close the escrow, auction_pda is closed by its account constraint
+        token_interface::close_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::CloseAccount {
+                    account: ctx.accounts.escrow_token_account.to_account_info(),
+                    destination: ctx.accounts.seller.to_account_info(),
+                    authority: ctx.accounts.auction_pda.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+        )?;
+
+
+        // Close the emptied bid escrow of SPL token auctions
+        if let Some(bid_escrow) = &ctx.accounts.bid_escrow {
+            token_interface::close_account(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::CloseAccount {
+                        account: bid_escrow.to_account_info(),
+                        destination: ctx.accounts.seller.to_account_info(),
+                        authority: ctx.accounts.auction_pda.to_account_info(),
+                    },
+                    &[&signer_seeds],
+                ),
+            )?;
+        }
+
+        Ok(())
+    }
+
+}
+
+#[derive(Accounts)]
+#[instruction(
+    collection_name: String,
+    collection_symbol: String,
+    ipfs_image_hash: String,
+    _metadata_uri: String,
+    _seller_fee_basis_points: u16,
+    creators: Vec<CollectionCreator>,
+)]
+pub struct MintCollection<'info> {
+    /// CHECK:
+    #[account(mut)]
+    pub mint: Signer<'info>,
+
+    /// CHECK:
+    #[account(mut)]
+    pub mint_authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Token Metadata only creates metadata and editions for SPL Token mints
+    #[account(address = token::ID @ MarketplaceError::MetadataRequiresSplToken)]
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// CHECK: created in the instruction, must be the ATA of mint_authority for mint
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address_with_program_id(
+            mint_authority.key,
+            mint.key,
+            token_program.key
+        ) @ MarketplaceError::WrongTokenAccount
+    )]
+    pub token_account: UncheckedAccount<'info>,
+
+    pub associated_token_program: Program<'info, associated_token::AssociatedToken>,
+
+    /// CHECK: pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    /// CHECK: created by Metaplex, must be the master edition PDA of mint
+    #[account(
+        mut,
+        address = find_master_edition_account(mint.key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    /// CHECK: created by Metaplex, must be the metadata PDA of mint
+    #[account(
+        mut,
+        address = find_metadata_account(mint.key).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: created by Metaplex, must be the authority record of collection_pda for mint
+    #[account(
+        mut,
+        address = find_collection_authority_account(
+            mint.key,
+            collection_pda.to_account_info().key
+        ).0 @ MarketplaceError::WrongCollectionAuthorityRecord
+    )]
+    collection_authority_record: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = mint_authority,
+        space = CollectionAccount::get_space(
+            collection_name,
+            collection_symbol,
+            ipfs_image_hash,
+            creators.len(),
+            0
+        ),
+        seeds = [
+            b"collection".as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    nft_name: String,
+    ipfs_image_hash: String,
+    _metadata_uri: String,
+)]
+pub struct MintNftFromCollection<'info> {
+    /// CHECK:
+    #[account(mut)]
+    pub mint: Signer<'info>,
+
+    /// CHECK:
+    #[account(mut)]
+    pub mint_authority: Signer<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Token Metadata only creates metadata and editions for SPL Token mints
+    #[account(address = token::ID @ MarketplaceError::MetadataRequiresSplToken)]
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// CHECK: created in the instruction, must be the ATA of mint_authority for mint
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address_with_program_id(
+            mint_authority.key,
+            mint.key,
+            token_program.key
+        ) @ MarketplaceError::WrongTokenAccount
+    )]
+    pub token_account: UncheckedAccount<'info>,
+
+    pub associated_token_program: Program<'info, associated_token::AssociatedToken>,
+
+    /// CHECK: pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = mint_authority,
+        space = NftAccount::get_space(
+            nft_name,
+            ipfs_image_hash
+        ),
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    /// CHECK: created by Metaplex, must be the master edition PDA of mint
+    #[account(
+        mut,
+        address = find_master_edition_account(mint.key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    /// CHECK: created by Metaplex, must be the metadata PDA of mint
+    #[account(
+        mut,
+        address = find_metadata_account(mint.key).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub collection_token_mint: Box<InterfaceAccount<'info, Mint>>,
+    
+    #[account(
+        mut,
+        constraint = !collection_pda.update_authority_released @ MarketplaceError::UpdateAuthorityReleased,
+        seeds = [
+            b"collection".as_ref(),
+            collection_token_mint.to_account_info().key.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    /// Minting rights of mint_authority, only when it isn't the collection owner.
+    /// Rights granted by a previous owner don't carry over to the new one
+    #[account(
+        constraint = minter_pda.granted_by == collection_pda.owner @ MarketplaceError::NotAllowedToMint,
+        seeds = [
+            b"minter".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            mint_authority.to_account_info().key.as_ref()
+        ],
+        bump = minter_pda.bump
+    )]
+    minter_pda: Option<Box<Account<'info, MinterAccount>>>,
+    
+    /// CHECK: must be the metadata PDA of collection_token_mint
+    #[account(
+        mut,
+        address = find_metadata_account(
+            collection_token_mint.to_account_info().key
+        ).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub collection_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: must be the master edition PDA of collection_token_mint
+    #[account(
+        mut,
+        address = find_master_edition_account(
+            collection_token_mint.to_account_info().key
+        ).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub collection_master_ed: UncheckedAccount<'info>,
+
+    /// CHECK: must be the authority record of collection_pda for collection_token_mint
+    #[account(
+        mut,
+        address = find_collection_authority_account(
+            collection_token_mint.to_account_info().key,
+            collection_pda.to_account_info().key
+        ).0 @ MarketplaceError::WrongCollectionAuthorityRecord
+    )]
+    collection_authority_record: UncheckedAccount<'info>,
+
+    /// CHECK: only for programmable collections, must be the token record PDA of token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.key,
+            token_account.key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable collections, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, validated by Metaplex
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, must be the rule set of the collection
+    #[account(address = collection_pda.rule_set.unwrap_or_default())]
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    _max_supply: u64,
+    phases: Vec<MintPhase>,
+)]
+pub struct ConfigureMintPhases<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner @ MarketplaceError::Unauthorized,
+        realloc = CollectionAccount::get_space(
+            collection_pda.name.clone(),
+            collection_pda.symbol.clone(),
+            collection_pda.ipfs_image_hash.clone(),
+            collection_pda.creators.len(),
+            phases.len()
+        ),
+        realloc::payer = owner,
+        realloc::zero = false,
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PublicMintNft<'info> {
+    pub mint_nft: MintNftFromCollection<'info>,
+
+    /// CHECK: checked against collection_pda.owner
+    #[account(
+        mut,
+        constraint = collection_owner.key() == mint_nft.collection_pda.owner @ MarketplaceError::WrongCollectionOwner
+    )]
+    pub collection_owner: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = mint_nft.mint_authority,
+        space = WalletMintsAccount::get_space(),
+        seeds = [
+            b"wallet_mints".as_ref(),
+            mint_nft.collection_pda.to_account_info().key.as_ref(),
+            mint_nft.mint_authority.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    wallet_mints_pda: Box<Account<'info, WalletMintsAccount>>,
+
+    /// Mint of the phase payment, only for SPL token phases
+    pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: minter token account in the payment mint, only for SPL token phases
+    #[account(mut)]
+    pub minter_payment_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: collection owner token account in the payment mint, checked when paid
+    #[account(mut)]
+    pub owner_payment_account: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    collection_name: String,
+    ipfs_image_hash: String,
+    _metadata_uri: String,
+)]
+pub struct UpdateCollection<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner @ MarketplaceError::Unauthorized,
+        constraint = !collection_pda.locked @ MarketplaceError::CollectionLocked,
+        constraint = !collection_pda.update_authority_released @ MarketplaceError::UpdateAuthorityReleased,
+        realloc = CollectionAccount::get_space(
+            collection_name.clone(),
+            collection_pda.symbol.clone(),
+            ipfs_image_hash.clone(),
+            collection_pda.creators.len(),
+            collection_pda.mint_phases.len()
+        ),
+        realloc::payer = owner,
+        realloc::zero = false,
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    /// CHECK: must be the metadata PDA of the collection mint
+    #[account(
+        mut,
+        address = find_metadata_account(&collection_pda.token_mint).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    nft_name: String,
+    ipfs_image_hash: String,
+    _metadata_uri: String,
+)]
+pub struct UpdateNft<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        has_one = owner @ MarketplaceError::Unauthorized,
+        constraint = !collection_pda.locked @ MarketplaceError::CollectionLocked,
+        constraint = !collection_pda.update_authority_released @ MarketplaceError::UpdateAuthorityReleased,
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        mut,
+        realloc = NftAccount::get_space(
+            nft_name.clone(),
+            ipfs_image_hash.clone()
+        ),
+        realloc::payer = owner,
+        realloc::zero = false,
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            nft_pda.token_mint.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    /// CHECK: must be the metadata PDA of the NFT mint
+    #[account(
+        mut,
+        address = find_metadata_account(&nft_pda.token_mint).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: only for programmable NFTs, must be the NFT mint
+    #[account(address = nft_pda.token_mint)]
+    pub mint: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, validated by Metaplex
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, must be the rule set of the collection
+    #[account(address = collection_pda.rule_set.unwrap_or_default())]
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeCollectionOwner<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner @ MarketplaceError::Unauthorized,
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptCollectionOwner<'info> {
+    pub new_owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = collection_pda.pending_owner == Some(new_owner.key()) @ MarketplaceError::NotPendingOwner,
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct GrantMintingRights<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        has_one = owner @ MarketplaceError::Unauthorized,
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    /// CHECK: any wallet can be granted minting rights
+    pub minter: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = MinterAccount::get_space(),
+        seeds = [
+            b"minter".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            minter.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    minter_pda: Box<Account<'info, MinterAccount>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeMintingRights<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        has_one = owner @ MarketplaceError::Unauthorized,
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    /// CHECK: the wallet losing its minting rights
+    pub minter: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [
+            b"minter".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            minter.to_account_info().key.as_ref()
+        ],
+        bump = minter_pda.bump
+    )]
+    minter_pda: Box<Account<'info, MinterAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseUpdateAuthority<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner @ MarketplaceError::Unauthorized,
+        constraint = !collection_pda.locked @ MarketplaceError::CollectionLocked,
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    /// CHECK: must be the metadata PDA of the collection mint
+    #[account(
+        mut,
+        address = find_metadata_account(&collection_pda.token_mint).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: only for rule sets, validated by Metaplex
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, must be the rule set of the collection
+    #[account(address = collection_pda.rule_set.unwrap_or_default())]
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct LockCollection<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner @ MarketplaceError::Unauthorized,
+        constraint = !collection_pda.locked @ MarketplaceError::CollectionLocked,
+        constraint = !collection_pda.update_authority_released @ MarketplaceError::UpdateAuthorityReleased,
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    /// CHECK: must be the metadata PDA of the collection mint
+    #[account(
+        mut,
+        address = find_metadata_account(&collection_pda.token_mint).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SignCreator<'info> {
+    pub creator: Signer<'info>,
+
+    #[account(
+        constraint = collection_pda.creators.iter().any(|c| c.address == creator.key()) @ MarketplaceError::NotCreator,
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    /// CHECK: metadata of the collection or of a NFT verified in it, checked in the instruction
+    #[account(mut)]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: UncheckedAccount<'info>,
+
+    /// CHECK: pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BurnNft<'info> {
+    #[account(mut)]
+    pub holder: Signer<'info>,
+
+    #[account(mut)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = holder,
+        token::token_program = token_program,
+        constraint = holder_token_account.amount == 1 @ MarketplaceError::NotNftHolder,
+    )]
+    pub holder_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        mut,
+        close = holder,
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    /// CHECK: must be the metadata PDA of mint, closed by Metaplex
+    #[account(
+        mut,
+        address = find_metadata_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: must be the master edition PDA of mint, closed by Metaplex
+    #[account(
+        mut,
+        address = find_master_edition_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    /// CHECK: must be the metadata PDA of the collection mint
+    #[account(
+        mut,
+        address = find_metadata_account(&collection_pda.token_mint).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub collection_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of holder_token_account, closed by Metaplex
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            holder_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct PrintEdition<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// Holder of the master edition token, Metaplex requires its signature to print
+    /// so every print is co-signed by the holder
+    pub master_holder: Signer<'info>,
+
+    pub master_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        token::mint = master_mint,
+        token::authority = master_holder,
+        token::token_program = token_program,
+        constraint = master_token_account.amount == 1 @ MarketplaceError::NotNftHolder,
+    )]
+    pub master_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            master_mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    /// CHECK: must be the metadata PDA of master_mint
+    #[account(
+        address = find_metadata_account(master_mint.to_account_info().key).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub master_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: must be the master edition PDA of master_mint
+    #[account(
+        mut,
+        address = find_master_edition_account(master_mint.to_account_info().key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    /// CHECK: edition marker PDA of the printed edition number, checked in the instruction
+    #[account(mut)]
+    pub edition_marker: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub edition_mint: Signer<'info>,
+
+    /// CHECK: created in the instruction, must be the ATA of buyer for edition_mint
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address_with_program_id(
+            buyer.key,
+            edition_mint.key,
+            token_program.key
+        ) @ MarketplaceError::WrongTokenAccount
+    )]
+    pub edition_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: created by Metaplex, must be the metadata PDA of edition_mint
+    #[account(
+        mut,
+        address = find_metadata_account(edition_mint.key).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub edition_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: created by Metaplex, must be the edition PDA of edition_mint
+    #[account(
+        mut,
+        address = find_master_edition_account(edition_mint.key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub edition_edition: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = buyer,
+        space = NftAccount::get_space(
+            nft_pda.name.clone(),
+            nft_pda.ipfs_image_hash.clone()
+        ),
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            edition_mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    edition_pda: Box<Account<'info, NftAccount>>,
+
+    /// CHECK: only for programmable NFTs, created by Metaplex, must be the token record PDA of edition_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            edition_mint.key,
+            edition_token_account.key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub edition_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+
+    pub system_program: Program<'info, System>,
+
+    /// Token Metadata only creates metadata and editions for SPL Token mints
+    #[account(address = token::ID @ MarketplaceError::MetadataRequiresSplToken)]
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub associated_token_program: Program<'info, associated_token::AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct SetEditionPrice<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        has_one = owner @ MarketplaceError::Unauthorized,
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            nft_pda.token_mint.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct ListNft<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program,
+    )]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    #[account(
+        init,
+        payer = seller,
+        space = ListingAccount::get_space(),
+        seeds = [
+            b"listing".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    listing_pda: Box<Account<'info, ListingAccount>>,
+
+    /// Legacy NFTs are held here while listed, without it they stay frozen in the seller wallet
+    #[account(
+        init,
+        payer = seller,
+        token::mint = mint,
+        token::authority = listing_pda,
+        token::token_program = token_program,
+        seeds = [
+            b"escrow".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    /// SPL token the NFT is priced in, lamports when missing
+    pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: only for programmable NFTs, must be the metadata PDA of mint
+    #[account(
+        mut,
+        address = find_metadata_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for NFTs that stay with the seller, must be the master edition PDA of mint
+    #[account(
+        address = find_master_edition_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub edition: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of seller_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            seller_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub owner_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, validated by Metaplex
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, must be the rule set of the collection
+    #[account(address = collection_pda.rule_set.unwrap_or_default())]
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for NFTs that stay with the seller, pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: Option<UncheckedAccount<'info>>,
+
+    pub rent: Sysvar<'info, Rent>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct DelistNft<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program,
+    )]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    #[account(
+        mut,
+        has_one = seller @ MarketplaceError::SellerMismatch,
+        close = seller,
+        seeds = [
+            b"listing".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump = listing_pda.bump
+    )]
+    listing_pda: Box<Account<'info, ListingAccount>>,
+
+    /// Only for escrow listings
+    #[account(
+        mut,
+        seeds = [
+            b"escrow".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump = listing_pda.escrow_bump
+    )]
+    escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: only for programmable NFTs, must be the metadata PDA of mint
+    #[account(
+        mut,
+        address = find_metadata_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for NFTs that stay with the seller, must be the master edition PDA of mint
+    #[account(
+        address = find_master_edition_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub edition: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of seller_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            seller_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub owner_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, validated by Metaplex
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, must be the rule set of the collection
+    #[account(address = collection_pda.rule_set.unwrap_or_default())]
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for NFTs that stay with the seller, pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct BuyNft<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: checked against listing_pda.seller
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program,
+    )]
+    pub buyer_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    #[account(
+        mut,
+        has_one = seller @ MarketplaceError::SellerMismatch,
+        close = seller,
+        seeds = [
+            b"listing".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump = listing_pda.bump
+    )]
+    listing_pda: Box<Account<'info, ListingAccount>>,
+
+    /// Only for escrow listings
+    #[account(
+        mut,
+        seeds = [
+            b"escrow".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump = listing_pda.escrow_bump
+    )]
+    escrow_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Only for NFTs that stay with the seller while listed
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program,
+    )]
+    pub seller_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Metaplex metadata of the NFT, creators are read from it
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            mpl_token_metadata::ID.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        seeds::program = mpl_token_metadata::ID,
+        bump
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury".as_ref()],
+        bump = marketplace.treasury_bump
+    )]
+    treasury: Box<Account<'info, TreasuryAccount>>,
+
+    /// Mint of the listing payment, only for SPL token listings
+    pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: buyer token account in the payment mint, only for SPL token listings
+    #[account(mut)]
+    pub buyer_payment_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: seller token account in the payment mint, checked when paid
+    #[account(mut)]
+    pub seller_payment_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: treasury token account in the payment mint, checked when paid
+    #[account(mut)]
+    pub treasury_payment_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for NFTs that stay with the seller, must be the master edition PDA of mint
+    #[account(
+        address = find_master_edition_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub edition: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of seller_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            &associated_token::get_associated_token_address_with_program_id(
+                seller.key,
+                mint.to_account_info().key,
+                token_program.key
+            )
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub owner_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of buyer_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            buyer_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub destination_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, validated by Metaplex
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, must be the rule set of the collection
+    #[account(address = collection_pda.rule_set.unwrap_or_default())]
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for NFTs that stay with the seller, pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: Option<UncheckedAccount<'info>>,
+
+    pub rent: Sysvar<'info, Rent>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub associated_token_program: Program<'info, associated_token::AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeMarketplace<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = MarketplaceAccount::get_space(),
+        seeds = [b"marketplace".as_ref()],
+        bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = TreasuryAccount::get_space(),
+        seeds = [b"treasury".as_ref()],
+        bump
+    )]
+    treasury: Box<Account<'info, TreasuryAccount>>,
+
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key()) @ MarketplaceError::Unauthorized
+    )]
+    pub program: Program<'info, crate::program::SolanaNft>,
+
+    /// Only the upgrade authority of the program can become the marketplace admin
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key()) @ MarketplaceError::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMarketplace<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin @ MarketplaceError::Unauthorized,
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasury<'info> {
+    pub admin: Signer<'info>,
+
+    /// CHECK: any account chosen by the admin
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+
+    #[account(
+        has_one = admin @ MarketplaceError::Unauthorized,
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury".as_ref()],
+        bump = marketplace.treasury_bump
+    )]
+    treasury: Box<Account<'info, TreasuryAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasuryTokens<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::token_program = token_program,
+    )]
+    pub destination: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub payment_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        has_one = admin @ MarketplaceError::Unauthorized,
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    #[account(
+        seeds = [b"treasury".as_ref()],
+        bump = marketplace.treasury_bump
+    )]
+    treasury: Box<Account<'info, TreasuryAccount>>,
+
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = treasury,
+        token::token_program = token_program,
+    )]
+    pub treasury_payment_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct MakeOffer<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    #[account(
+        init,
+        payer = bidder,
+        space = OfferAccount::get_space(),
+        seeds = [
+            b"offer".as_ref(),
+            nft_pda.to_account_info().key.as_ref(),
+            bidder.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    offer_pda: Box<Account<'info, OfferAccount>>,
+
+    #[account(
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    /// SPL token the offer is made in, lamports when missing
+    pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: bidder token account in the payment mint, only for SPL token offers
+    #[account(mut)]
+    pub bidder_payment_account: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        init,
+        payer = bidder,
+        token::mint = payment_mint,
+        token::authority = offer_pda,
+        token::token_program = token_program,
+        seeds = [
+            b"offer_escrow".as_ref(),
+            offer_pda.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    offer_escrow: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub rent: Sysvar<'info, Rent>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    pub authority: Signer<'info>,
+
+    /// CHECK: checked against offer_pda.bidder
+    #[account(mut)]
+    pub bidder: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        has_one = bidder @ MarketplaceError::WrongBidder,
+        close = bidder,
+        seeds = [
+            b"offer".as_ref(),
+            nft_pda.to_account_info().key.as_ref(),
+            bidder.to_account_info().key.as_ref()
+        ],
+        bump = offer_pda.bump
+    )]
+    offer_pda: Box<Account<'info, OfferAccount>>,
+
+    /// CHECK: only used to derive offer_pda
+    nft_pda: UncheckedAccount<'info>,
+
+    /// Mint of the offer payment, only for SPL token offers
+    pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"offer_escrow".as_ref(),
+            offer_pda.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    offer_escrow: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: bidder token account in the payment mint, checked when refunded
+    #[account(mut)]
+    pub bidder_payment_account: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOffer<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    /// CHECK: checked against offer_pda.bidder
+    #[account(mut)]
+    pub bidder: UncheckedAccount<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program,
+    )]
+    pub seller_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = mint,
+        associated_token::authority = bidder,
+        associated_token::token_program = token_program,
+    )]
+    pub bidder_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    #[account(
+        mut,
+        has_one = bidder @ MarketplaceError::WrongBidder,
+        close = bidder,
+        seeds = [
+            b"offer".as_ref(),
+            nft_pda.to_account_info().key.as_ref(),
+            bidder.to_account_info().key.as_ref()
+        ],
+        bump = offer_pda.bump
+    )]
+    offer_pda: Box<Account<'info, OfferAccount>>,
+
+    /// CHECK: Metaplex metadata of the NFT, creators are read from it
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            mpl_token_metadata::ID.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        seeds::program = mpl_token_metadata::ID,
+        bump
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury".as_ref()],
+        bump = marketplace.treasury_bump
+    )]
+    treasury: Box<Account<'info, TreasuryAccount>>,
+
+    /// Mint of the offer payment, only for SPL token offers
+    pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"offer_escrow".as_ref(),
+            offer_pda.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    offer_escrow: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: seller token account in the payment mint, checked when paid
+    #[account(mut)]
+    pub seller_payment_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: treasury token account in the payment mint, checked when paid
+    #[account(mut)]
+    pub treasury_payment_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the master edition PDA of mint
+    #[account(
+        address = find_master_edition_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub edition: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of seller_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            seller_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub owner_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of bidder_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            bidder_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub destination_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, validated by Metaplex
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, must be the rule set of the collection
+    #[account(address = collection_pda.rule_set.unwrap_or_default())]
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: Option<UncheckedAccount<'info>>,
+
+    pub rent: Sysvar<'info, Rent>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub associated_token_program: Program<'info, associated_token::AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct MakeCollectionBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    #[account(
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        init,
+        payer = bidder,
+        space = CollectionBidAccount::get_space(),
+        seeds = [
+            b"collection_bid".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            bidder.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    collection_bid_pda: Box<Account<'info, CollectionBidAccount>>,
+
+    #[account(
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelCollectionBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = bidder @ MarketplaceError::WrongBidder,
+        close = bidder,
+        seeds = [
+            b"collection_bid".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            bidder.to_account_info().key.as_ref()
+        ],
+        bump = collection_bid_pda.bump
+    )]
+    collection_bid_pda: Box<Account<'info, CollectionBidAccount>>,
+
+    /// CHECK: only used to derive collection_bid_pda
+    collection_pda: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FillCollectionBid<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    /// CHECK: checked against collection_bid_pda.bidder
+    #[account(mut)]
+    pub bidder: UncheckedAccount<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program,
+    )]
+    pub seller_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = mint,
+        associated_token::authority = bidder,
+        associated_token::token_program = token_program,
+    )]
+    pub bidder_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        constraint = nft_pda.collection_mint == collection_bid_pda.collection_mint @ MarketplaceError::WrongCollection,
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    #[account(
+        mut,
+        has_one = bidder @ MarketplaceError::WrongBidder,
+        seeds = [
+            b"collection_bid".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            bidder.to_account_info().key.as_ref()
+        ],
+        bump = collection_bid_pda.bump
+    )]
+    collection_bid_pda: Box<Account<'info, CollectionBidAccount>>,
+
+    /// CHECK: Metaplex metadata of the NFT, creators and collection are read from it
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            mpl_token_metadata::ID.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        seeds::program = mpl_token_metadata::ID,
+        bump
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury".as_ref()],
+        bump = marketplace.treasury_bump
+    )]
+    treasury: Box<Account<'info, TreasuryAccount>>,
+
+    /// CHECK: only for programmable NFTs, must be the master edition PDA of mint
+    #[account(
+        address = find_master_edition_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub edition: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of seller_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            seller_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub owner_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of bidder_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            bidder_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub destination_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, validated by Metaplex
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, must be the rule set of the collection
+    #[account(address = collection_pda.rule_set.unwrap_or_default())]
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: Option<UncheckedAccount<'info>>,
+
+    pub rent: Sysvar<'info, Rent>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub associated_token_program: Program<'info, associated_token::AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct CreateAuction<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program,
+    )]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    #[account(
+        init,
+        payer = seller,
+        space = AuctionAccount::get_space(),
+        seeds = [
+            b"auction".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    auction_pda: Box<Account<'info, AuctionAccount>>,
+
+    #[account(
+        init,
+        payer = seller,
+        token::mint = mint,
+        token::authority = auction_pda,
+        token::token_program = token_program,
+        seeds = [
+            b"auction_escrow".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    escrow_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    /// SPL token the auction is bid in, lamports when missing
+    pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = seller,
+        token::mint = payment_mint,
+        token::authority = auction_pda,
+        token::token_program = token_program,
+        seeds = [
+            b"bid_escrow".as_ref(),
+            auction_pda.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    bid_escrow: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: only for programmable NFTs, must be the metadata PDA of mint
+    #[account(
+        mut,
+        address = find_metadata_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the master edition PDA of mint
+    #[account(
+        address = find_master_edition_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub edition: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of seller_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            seller_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub owner_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of escrow_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            escrow_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub destination_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, validated by Metaplex
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, must be the rule set of the collection
+    #[account(address = collection_pda.rule_set.unwrap_or_default())]
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: Option<UncheckedAccount<'info>>,
+
+    pub rent: Sysvar<'info, Rent>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub associated_token_program: Program<'info, associated_token::AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceBid<'info> {
+    #[account(mut)]
+    pub bidder: Signer<'info>,
+
+    /// CHECK: checked against auction_pda.highest_bidder when there is a bid to refund
+    #[account(mut)]
+    pub previous_bidder: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"auction".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump = auction_pda.bump
+    )]
+    auction_pda: Box<Account<'info, AuctionAccount>>,
+
+    /// CHECK: only used to derive auction_pda
+    nft_pda: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    /// Mint of the auction payment, only for SPL token auctions
+    pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"bid_escrow".as_ref(),
+            auction_pda.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    bid_escrow: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: bidder token account in the payment mint, only for SPL token auctions
+    #[account(mut)]
+    pub bidder_payment_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: outbid bidder token account in the payment mint, checked when refunded
+    #[account(mut)]
+    pub previous_bidder_payment_account: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SettleAuction<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: checked against auction_pda.seller
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+
+    /// CHECK: checked against auction_pda.highest_bidder, or the seller when nobody bid
+    pub winner: UncheckedAccount<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = mint,
+        associated_token::authority = winner,
+        associated_token::token_program = token_program,
+    )]
+    pub winner_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    #[account(
+        mut,
+        has_one = seller @ MarketplaceError::SellerMismatch,
+        close = seller,
+        seeds = [
+            b"auction".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump = auction_pda.bump
+    )]
+    auction_pda: Box<Account<'info, AuctionAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"auction_escrow".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump = auction_pda.escrow_bump
+    )]
+    escrow_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Metaplex metadata of the NFT, creators are read from it
+    #[account(
+        mut,
+        seeds = [
+            b"metadata".as_ref(),
+            mpl_token_metadata::ID.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        seeds::program = mpl_token_metadata::ID,
+        bump
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"marketplace".as_ref()],
+        bump = marketplace.bump
+    )]
+    marketplace: Box<Account<'info, MarketplaceAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury".as_ref()],
+        bump = marketplace.treasury_bump
+    )]
+    treasury: Box<Account<'info, TreasuryAccount>>,
+
+    /// Mint of the auction payment, only for SPL token auctions
+    pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"bid_escrow".as_ref(),
+            auction_pda.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    bid_escrow: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: seller token account in the payment mint, checked when paid
+    #[account(mut)]
+    pub seller_payment_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: treasury token account in the payment mint, checked when paid
+    #[account(mut)]
+    pub treasury_payment_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the master edition PDA of mint
+    #[account(
+        address = find_master_edition_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub edition: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of escrow_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            escrow_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub owner_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of winner_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            winner_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub destination_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, validated by Metaplex
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, must be the rule set of the collection
+    #[account(address = collection_pda.rule_set.unwrap_or_default())]
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: Option<UncheckedAccount<'info>>,
+
+    pub rent: Sysvar<'info, Rent>,
+
+    pub system_program: Program<'info, System>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub associated_token_program: Program<'info, associated_token::AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAuction<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program,
+    )]
+    pub seller_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            b"collection".as_ref(),
+            collection_pda.token_mint.as_ref()
+        ],
+        bump = collection_pda.bump
+    )]
+    collection_pda: Box<Account<'info, CollectionAccount>>,
+
+    #[account(
+        seeds = [
+            b"nft".as_ref(),
+            collection_pda.to_account_info().key.as_ref(),
+            mint.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    nft_pda: Box<Account<'info, NftAccount>>,
+
+    #[account(
+        mut,
+        has_one = seller @ MarketplaceError::SellerMismatch,
+        close = seller,
+        seeds = [
+            b"auction".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump = auction_pda.bump
+    )]
+    auction_pda: Box<Account<'info, AuctionAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"auction_escrow".as_ref(),
+            nft_pda.to_account_info().key.as_ref()
+        ],
+        bump = auction_pda.escrow_bump
+    )]
+    escrow_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"bid_escrow".as_ref(),
+            auction_pda.to_account_info().key.as_ref()
+        ],
+        bump
+    )]
+    bid_escrow: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: only for programmable NFTs, must be the metadata PDA of mint
+    #[account(
+        mut,
+        address = find_metadata_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMetadataAccount
+    )]
+    pub metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the master edition PDA of mint
+    #[account(
+        address = find_master_edition_account(mint.to_account_info().key).0 @ MarketplaceError::WrongMasterEditionAccount
+    )]
+    pub edition: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of escrow_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            escrow_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub owner_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, must be the token record PDA of seller_token_account
+    #[account(
+        mut,
+        address = find_token_record_account(
+            mint.to_account_info().key,
+            seller_token_account.to_account_info().key
+        ).0 @ MarketplaceError::WrongTokenRecordAccount
+    )]
+    pub destination_token_record: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, validated by Metaplex
+    pub authorization_rules_program: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for rule sets, must be the rule set of the collection
+    #[account(address = collection_pda.rule_set.unwrap_or_default())]
+    pub authorization_rules: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the instructions sysvar
+    #[account(address = sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: only for programmable NFTs, pinned to the Metaplex Token Metadata program
+    #[account(address = mpl_token_metadata::ID @ MarketplaceError::WrongTokenMetadataProgram)]
+    pub token_metadata_program: Option<UncheckedAccount<'info>>,
 
     pub system_program: Program<'info, System>,
 
-    pub token_program: Program<'info, token::Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+
+    pub associated_token_program: Program<'info, associated_token::AssociatedToken>,
+}
+
+#[account]
+#[derive(Default)]
+pub struct CollectionAccount {
+    pub owner: Pubkey,
+    pub token_mint: Pubkey,
+    pub name: String,
+    pub symbol: String,
+    pub ipfs_image_hash: String,
+    pub seller_fee_basis_points: u16,
+    pub creators: Vec<CollectionCreator>,
+    pub minted_count: u64,
+    pub burned_count: u64,
+    pub max_supply: u64,
+    pub mint_phases: Vec<MintPhase>,
+    pub phases_version: u32,
+    pub pending_owner: Option<Pubkey>,
+    pub locked: bool,
+    pub update_authority_released: bool,
+    pub programmable: bool,
+    pub rule_set: Option<Pubkey>,
+    pub bump: u8,
+    pub created: i64,
+}
+
+impl CollectionAccount {
+    fn get_space(
+        name: String,
+        symbol: String,
+        ipfs_image_hash: String,
+        creators: usize,
+        mint_phases: usize
+    ) -> usize {
+        return DISCRIMINATOR_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + Self::get_string_size(name)
+            + Self::get_string_size(symbol)
+            + Self::get_string_size(ipfs_image_hash)
+            + U16_LENGTH
+            + VEC_PREFIX_LENGTH
+            + creators * CollectionCreator::LENGTH
+            + U64_LENGTH
+            + U64_LENGTH
+            + U64_LENGTH
+            + VEC_PREFIX_LENGTH
+            + mint_phases * MintPhase::LENGTH
+            + U32_LENGTH
+            + OPTION_PREFIX_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + BOOL_LENGTH
+            + BOOL_LENGTH
+            + BOOL_LENGTH
+            + OPTION_PREFIX_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + U8_LENGTH
+            + I64_LENGTH;
+    }
+    fn get_string_size(property: String) -> usize {
+        return property.as_bytes().len() + STRING_PREFIX_LENGTH;
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct CollectionCreator {
+    pub address: Pubkey,
+    pub share: u8,
+}
+
+impl CollectionCreator {
+    const LENGTH: usize = PUBLIC_KEY_LENGTH + U8_LENGTH;
+
+    fn to_metaplex(creators: &[CollectionCreator]) -> Vec<mpl_token_metadata::state::Creator> {
+        return creators
+            .iter()
+            .map(|c| mpl_token_metadata::state::Creator {
+                address: c.address,
+                verified: false,
+                share: c.share,
+            })
+            .collect();
+    }
+}
+
+/// pNFT options of a collection, the rule set only applies to programmable collections.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct ProgrammableConfig {
+    pub programmable: bool,
+    pub rule_set: Option<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct MintPhase {
+    pub price: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub per_wallet_limit: u32,
+    pub merkle_root: Option<[u8; 32]>,
+    pub payment_mint: Option<Pubkey>,
+}
+
+impl MintPhase {
+    const LENGTH: usize = U64_LENGTH
+        + I64_LENGTH
+        + I64_LENGTH
+        + U32_LENGTH
+        + OPTION_PREFIX_LENGTH
+        + HASH_LENGTH
+        + OPTION_PREFIX_LENGTH
+        + PUBLIC_KEY_LENGTH;
+}
+
+#[account]
+#[derive(Default)]
+pub struct WalletMintsAccount {
+    pub wallet: Pubkey,
+    pub collection: Pubkey,
+    pub phase_mints: [u32; MAX_MINT_PHASES],
+    pub phases_version: u32,
+    pub bump: u8,
+}
+
+impl WalletMintsAccount {
+    fn get_space() -> usize {
+        return DISCRIMINATOR_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + U32_LENGTH * MAX_MINT_PHASES
+            + U32_LENGTH
+            + U8_LENGTH;
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct MinterAccount {
+    pub collection: Pubkey,
+    pub minter: Pubkey,
+    pub granted_by: Pubkey,
+    pub bump: u8,
+    pub created: i64,
+}
+
+impl MinterAccount {
+    fn get_space() -> usize {
+        return DISCRIMINATOR_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + U8_LENGTH
+            + I64_LENGTH;
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct NftAccount {
+    pub token_mint: Pubkey,
+    pub collection_mint: Pubkey,
+    pub name: String,
+    pub ipfs_image_hash: String,
+    pub edition: u64,
+    pub edition_price: u64,
+    pub created: i64,
+}
+
+impl NftAccount {
+    fn get_space(
+        name: String,
+        ipfs_image_hash: String
+    ) -> usize {
+        return DISCRIMINATOR_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + Self::get_string_size(name)
+            + Self::get_string_size(ipfs_image_hash)
+            + U64_LENGTH
+            + U64_LENGTH
+            + I64_LENGTH;
+    }
+    fn get_string_size(property: String) -> usize {
+        return property.as_bytes().len() + STRING_PREFIX_LENGTH;
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct ListingAccount {
+    pub seller: Pubkey,
+    pub token_mint: Pubkey,
+    pub price: u64,
+    pub kind: ListingKind,
+    pub payment_mint: Option<Pubkey>,
+    pub custody: ListingCustody,
+    pub bump: u8,
+    pub escrow_bump: u8,
+    pub created: i64,
+}
+
+impl ListingAccount {
+    fn get_space() -> usize {
+        return DISCRIMINATOR_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + U64_LENGTH
+            + ListingKind::LENGTH
+            + OPTION_PREFIX_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + ListingCustody::LENGTH
+            + U8_LENGTH
+            + U8_LENGTH
+            + I64_LENGTH;
+    }
+
+    /// Price of the listing at `now`. Dutch listings fall linearly from `price`
+    /// to their floor price between their start and end time.
+    fn current_price(&self, now: i64) -> u64 {
+        match self.kind {
+            ListingKind::FixedPrice => self.price,
+            ListingKind::Dutch { floor_price, start_time, end_time } => {
+                if now <= start_time {
+                    return self.price;
+                }
+                if now >= end_time {
+                    return floor_price;
+                }
+                let drop = (self.price - floor_price) as u128
+                    * (now - start_time) as u128
+                    / (end_time - start_time) as u128;
+                self.price - drop as u64
+            }
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum ListingKind {
+    FixedPrice,
+    Dutch {
+        floor_price: u64,
+        start_time: i64,
+        end_time: i64,
+    },
+}
+
+impl Default for ListingKind {
+    fn default() -> Self {
+        ListingKind::FixedPrice
+    }
+}
+
+impl ListingKind {
+    const LENGTH: usize = U8_LENGTH + U64_LENGTH + I64_LENGTH + I64_LENGTH;
+}
+
+/// Where a listed NFT is held until it's sold or delisted.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ListingCustody {
+    /// Moved into the escrow owned by listing_pda
+    Escrow,
+    /// Kept in the seller wallet, delegated to listing_pda and frozen
+    Frozen,
+    /// Programmable NFT kept in the seller wallet, locked by listing_pda as sale delegate
+    SaleDelegate,
+}
+
+impl Default for ListingCustody {
+    fn default() -> Self {
+        ListingCustody::Escrow
+    }
+}
+
+impl ListingCustody {
+    const LENGTH: usize = U8_LENGTH;
+}
+
+#[account]
+#[derive(Default)]
+pub struct MarketplaceAccount {
+    pub admin: Pubkey,
+    pub fee_basis_points: u16,
+    pub treasury_bump: u8,
+    pub paused: bool,
+    pub payment_mints: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl MarketplaceAccount {
+    fn get_space() -> usize {
+        return DISCRIMINATOR_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + U16_LENGTH
+            + U8_LENGTH
+            + BOOL_LENGTH
+            + VEC_PREFIX_LENGTH
+            + PUBLIC_KEY_LENGTH * MAX_PAYMENT_MINTS
+            + U8_LENGTH;
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct TreasuryAccount {
+    pub bump: u8,
+}
+
+impl TreasuryAccount {
+    fn get_space() -> usize {
+        return DISCRIMINATOR_LENGTH
+            + U8_LENGTH;
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct OfferAccount {
+    pub bidder: Pubkey,
+    pub token_mint: Pubkey,
+    pub price: u64,
+    pub expiry: i64,
+    pub payment_mint: Option<Pubkey>,
+    pub bump: u8,
+    pub created: i64,
+}
+
+impl OfferAccount {
+    fn get_space() -> usize {
+        return DISCRIMINATOR_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + U64_LENGTH
+            + I64_LENGTH
+            + OPTION_PREFIX_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + U8_LENGTH
+            + I64_LENGTH;
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct CollectionBidAccount {
+    pub bidder: Pubkey,
+    pub collection_mint: Pubkey,
+    pub price: u64,
+    pub quantity: u32,
+    pub bump: u8,
+    pub created: i64,
+}
+
+impl CollectionBidAccount {
+    fn get_space() -> usize {
+        return DISCRIMINATOR_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + U64_LENGTH
+            + U32_LENGTH
+            + U8_LENGTH
+            + I64_LENGTH;
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct AuctionAccount {
+    pub seller: Pubkey,
+    pub token_mint: Pubkey,
+    pub reserve_price: u64,
+    pub min_increment: u64,
+    pub end_time: i64,
+    pub extension_seconds: i64,
+    pub highest_bidder: Pubkey,
+    pub highest_bid: u64,
+    pub payment_mint: Option<Pubkey>,
+    pub bump: u8,
+    pub escrow_bump: u8,
+    pub created: i64,
+}
+
+impl AuctionAccount {
+    fn get_space() -> usize {
+        return DISCRIMINATOR_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + U64_LENGTH
+            + U64_LENGTH
+            + I64_LENGTH
+            + I64_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + U64_LENGTH
+            + OPTION_PREFIX_LENGTH
+            + PUBLIC_KEY_LENGTH
+            + U8_LENGTH
+            + U8_LENGTH
+            + I64_LENGTH;
+    }
+}
+
+/// Mints a new NFT and verifies it as a member of the collection.
+fn mint_nft(
+    accounts: &mut MintNftFromCollection,
+    nft_name: String,
+    ipfs_image_hash: String,
+    metadata_uri: String,
+    edition_max_supply: Option<u64>,
+    edition_price: u64,
+) -> Result<()> {
+    validate_metadata(&nft_name, &accounts.collection_pda.symbol, &metadata_uri, &ipfs_image_hash)?;
+
+    // A max_supply of 0 means the collection has no cap
+    let max_supply = accounts.collection_pda.max_supply;
+    require!(
+        max_supply == 0 || accounts.collection_pda.minted_count < max_supply,
+        MarketplaceError::SupplyExhausted
+    );
+
+    let coll_mint = accounts.collection_pda.token_mint;
+    let coll_bump = accounts.collection_pda.bump;
+    let _signer_seeds = [
+        b"collection".as_ref(),
+        coll_mint.as_ref(),
+        &[coll_bump],
+    ];
+
+    // Legacy instructions are rejected by Metaplex for pNFTs, which are verified with Verify instead
+    if accounts.collection_pda.programmable {
+        create_programmable_nft(accounts, &nft_name, &metadata_uri, edition_max_supply, &_signer_seeds)?;
+    } else {
+        create_legacy_nft(accounts, &nft_name, &metadata_uri, edition_max_supply, &_signer_seeds)?;
+    }
+
+    // Set NFT data
+    let clock: Clock = Clock::get()?;
+
+    accounts.nft_pda.token_mint = accounts.mint.key();
+    accounts.nft_pda.collection_mint = accounts.collection_token_mint.key();
+    accounts.nft_pda.name = nft_name;
+    accounts.nft_pda.ipfs_image_hash = ipfs_image_hash;
+    accounts.nft_pda.edition = 0;
+    accounts.nft_pda.edition_price = edition_price;
+    accounts.nft_pda.created = clock.unix_timestamp;
+
+    accounts.collection_pda.minted_count += 1;
+
+    Ok(())
+}
+
+/// Creates a legacy NFT with the SPL Token program and hands its metadata over to collection_pda.
+fn create_legacy_nft(
+    accounts: &MintNftFromCollection,
+    nft_name: &str,
+    metadata_uri: &str,
+    edition_max_supply: Option<u64>,
+    signer_seeds: &[&[u8]],
+) -> Result<()> {
+    // Create an account to become it in the NFT token_mint
+    system_program::create_account(
+        CpiContext::new(
+            accounts.system_program.to_account_info(),
+            system_program::CreateAccount {
+                from: accounts.mint_authority.to_account_info(),
+                to: accounts.mint.to_account_info(),
+            },
+        ),
+        accounts.rent.minimum_balance(token::Mint::LEN),
+        token::Mint::LEN as u64,
+        &accounts.token_program.key(),
+    )?;
+
+    // Create the token_mint for the NFT
+    token_interface::initialize_mint(
+        CpiContext::new(
+            accounts.token_program.to_account_info(),
+            token_interface::InitializeMint {
+                mint: accounts.mint.to_account_info(),
+                rent: accounts.rent.to_account_info(),
+            },
+        ),
+        0,
+        &accounts.mint_authority.key(),
+        Some(&accounts.mint_authority.key()),
+    )?;
+
+    // Create ATA for mint_authority
+    associated_token::create(CpiContext::new(
+        accounts.associated_token_program.to_account_info(),
+        associated_token::Create {
+            payer: accounts.mint_authority.to_account_info(),
+            associated_token: accounts.token_account.to_account_info(),
+            authority: accounts.mint_authority.to_account_info(),
+            mint: accounts.mint.to_account_info(),
+            system_program: accounts.system_program.to_account_info(),
+            token_program: accounts.token_program.to_account_info(),
+        },
+    ))?;
+
+    // Mint NFT
+    token_interface::mint_to(
+        CpiContext::new(
+            accounts.token_program.to_account_info(),
+            token_interface::MintTo {
+                mint: accounts.mint.to_account_info(),
+                to: accounts.token_account.to_account_info(),
+                authority: accounts.mint_authority.to_account_info(),
+            },
+        ),
+        1,
+    )?;
+
+    // Create metadata for the NFT token_mint
+    invoke(
+        &create_metadata_accounts_v3(
+            accounts.token_metadata_program.key(),
+            accounts.metadata.key(),
+            accounts.mint.key(),
+            accounts.mint_authority.key(),
+            accounts.payer.key(),
+            accounts.payer.key(),
+            nft_name.to_string(),
+            accounts.collection_pda.symbol.to_string(),
+            metadata_uri.to_string(),
+            None,
+            0,
+            true,
+            true,
+            None,
+            None,
+            None,
+        ),
+        &[
+            accounts.metadata.to_account_info(),
+            accounts.mint.to_account_info(),
+            accounts.mint_authority.to_account_info(),
+            accounts.payer.to_account_info(),
+            accounts.token_metadata_program.to_account_info(),
+            accounts.token_program.to_account_info(),
+            accounts.system_program.to_account_info(),
+            accounts.rent.to_account_info(),
+        ],
+    )?;
+
+    // Create master edition for the NFT
+    invoke(
+        &create_master_edition_v3(
+            accounts.token_metadata_program.key(),
+            accounts.master_edition.key(),
+            accounts.mint.key(),
+            accounts.payer.key(),
+            accounts.mint_authority.key(),
+            accounts.metadata.key(),
+            accounts.payer.key(),
+            edition_max_supply,
+        ),
+        &[
+            accounts.master_edition.to_account_info(),
+            accounts.mint.to_account_info(),
+            accounts.mint_authority.to_account_info(),
+            accounts.payer.to_account_info(),
+            accounts.metadata.to_account_info(),
+            accounts.token_metadata_program.to_account_info(),
+            accounts.token_program.to_account_info(),
+            accounts.system_program.to_account_info(),
+            accounts.rent.to_account_info(),
+        ],
+    )?;
+
+    // Change update authority to nft_pda and set metadata inherited from the collection
+    let creators = &accounts.collection_pda.creators;
+
+    let data = mpl_token_metadata::state::DataV2 {
+        name: nft_name.to_string(),
+        symbol: accounts.collection_pda.symbol.clone(),
+        uri: metadata_uri.to_string(),
+        collection: None,
+        creators: Some(CollectionCreator::to_metaplex(creators)),
+        seller_fee_basis_points: accounts.collection_pda.seller_fee_basis_points,
+        uses: None,
+    };
+
+    invoke(
+        &update_metadata_accounts_v2(
+            accounts.token_metadata_program.key(),
+            accounts.metadata.key(),
+            accounts.payer.key(),
+            Some(accounts.collection_pda.key()),
+            Some(data),
+            Some(true),
+            Some(!accounts.collection_pda.locked),
+        ),
+        &[
+            accounts.token_metadata_program.to_account_info(),
+            accounts.metadata.to_account_info(),
+            accounts.payer.to_account_info(),
+        ],
+    )?;
+
+    // Sign Metadata (verify creator)
+    let creators = &accounts.collection_pda.creators;
+
+    if creators.iter().any(|c| c.address == accounts.mint_authority.key()) {
+        invoke_signed(
+            &sign_metadata(
+                accounts.token_metadata_program.key(),
+                accounts.metadata.key(),
+                accounts.mint_authority.key(),
+            ),
+            &[
+                accounts.metadata.to_account_info(),
+                accounts.mint_authority.to_account_info(),
+            ],
+            &[signer_seeds],
+        )?;
+    }
+
+    // Verify master edition
+    invoke_signed(
+        &set_and_verify_collection(
+            accounts.token_metadata_program.key(),
+            accounts.metadata.key(),
+            accounts.collection_pda.key(),
+            accounts.payer.key(),
+            accounts.collection_pda.key(),
+            accounts.collection_token_mint.key(),
+            accounts.collection_metadata.key(),
+            accounts.collection_master_ed.key(),
+            None,
+        ),
+        &[
+            accounts.metadata.to_account_info(),
+            accounts.collection_pda.to_account_info(),
+            accounts.payer.to_account_info(),
+            accounts.collection_pda.to_account_info(),
+            accounts.collection_token_mint.to_account_info(),
+            accounts.collection_metadata.to_account_info(),
+            accounts.collection_master_ed.to_account_info(),
+        ],
+        &[signer_seeds],
+    )?;
+
+    Ok(())
+}
+
+/// Creates and mints a programmable NFT whose metadata belongs to collection_pda from the start.
+fn create_programmable_nft(
+    accounts: &MintNftFromCollection,
+    nft_name: &str,
+    metadata_uri: &str,
+    edition_max_supply: Option<u64>,
+    signer_seeds: &[&[u8]],
+) -> Result<()> {
+    let token_record = programmable_account(&accounts.token_record)?;
+    let sysvar_instructions = programmable_account(&accounts.sysvar_instructions)?;
+
+    // A max supply of None lets the owner print unlimited editions
+    let print_supply = match edition_max_supply {
+        None => PrintSupply::Unlimited,
+        Some(0) => PrintSupply::Zero,
+        Some(max_supply) => PrintSupply::Limited(max_supply),
+    };
+
+    let asset_data = AssetData {
+        name: nft_name.to_string(),
+        symbol: accounts.collection_pda.symbol.clone(),
+        uri: metadata_uri.to_string(),
+        seller_fee_basis_points: accounts.collection_pda.seller_fee_basis_points,
+        creators: Some(CollectionCreator::to_metaplex(&accounts.collection_pda.creators)),
+        primary_sale_happened: true,
+        is_mutable: !accounts.collection_pda.locked,
+        token_standard: TokenStandard::ProgrammableNonFungible,
+        collection: Some(Collection {
+            verified: false,
+            key: accounts.collection_token_mint.key(),
+        }),
+        uses: None,
+        collection_details: None,
+        rule_set: accounts.collection_pda.rule_set,
+    };
+
+    // Create mint, metadata and master edition of the pNFT
+    let create_ix = CreateBuilder::new()
+        .metadata(accounts.metadata.key())
+        .master_edition(accounts.master_edition.key())
+        .mint(accounts.mint.key())
+        .authority(accounts.mint_authority.key())
+        .payer(accounts.payer.key())
+        .update_authority(accounts.collection_pda.key())
+        .initialize_mint(true)
+        .update_authority_as_signer(true)
+        .build(CreateArgs::V1 {
+            asset_data,
+            decimals: Some(0),
+            print_supply: Some(print_supply),
+        })
+        .map_err(|_| error!(MarketplaceError::MissingProgrammableAccount))?
+        .instruction();
+
+    invoke_signed(
+        &create_ix,
+        &[
+            accounts.metadata.to_account_info(),
+            accounts.master_edition.to_account_info(),
+            accounts.mint.to_account_info(),
+            accounts.mint_authority.to_account_info(),
+            accounts.payer.to_account_info(),
+            accounts.collection_pda.to_account_info(),
+            accounts.system_program.to_account_info(),
+            sysvar_instructions.clone(),
+            accounts.token_program.to_account_info(),
+            accounts.token_metadata_program.to_account_info(),
+        ],
+        &[signer_seeds],
+    )?;
+
+    // Mint the pNFT to the ATA of mint_authority, Metaplex creates the ATA and token record
+    let mut mint_builder = MintBuilder::new();
+    mint_builder
+        .token(accounts.token_account.key())
+        .token_owner(accounts.mint_authority.key())
+        .metadata(accounts.metadata.key())
+        .master_edition(accounts.master_edition.key())
+        .token_record(token_record.key())
+        .mint(accounts.mint.key())
+        .authority(accounts.collection_pda.key())
+        .payer(accounts.payer.key());
+    if let Some(authorization_rules) = &accounts.authorization_rules {
+        mint_builder
+            .authorization_rules_program(programmable_account(&accounts.authorization_rules_program)?.key())
+            .authorization_rules(authorization_rules.key());
+    }
+    let mint_ix = mint_builder
+        .build(MintArgs::V1 {
+            amount: 1,
+            authorization_data: None,
+        })
+        .map_err(|_| error!(MarketplaceError::MissingProgrammableAccount))?
+        .instruction();
+
+    let mut mint_accounts = vec![
+        accounts.token_account.to_account_info(),
+        accounts.mint_authority.to_account_info(),
+        accounts.metadata.to_account_info(),
+        accounts.master_edition.to_account_info(),
+        token_record,
+        accounts.mint.to_account_info(),
+        accounts.collection_pda.to_account_info(),
+        accounts.payer.to_account_info(),
+        accounts.system_program.to_account_info(),
+        sysvar_instructions.clone(),
+        accounts.token_program.to_account_info(),
+        accounts.associated_token_program.to_account_info(),
+        accounts.token_metadata_program.to_account_info(),
+    ];
+    if let Some(authorization_rules) = &accounts.authorization_rules {
+        mint_accounts.push(programmable_account(&accounts.authorization_rules_program)?);
+        mint_accounts.push(authorization_rules.to_account_info());
+    }
+
+    invoke_signed(&mint_ix, &mint_accounts, &[signer_seeds])?;
 
-    /// CHECK:
-    #[account(mut)]
-    pub token_account: UncheckedAccount<'info>,
+    // Verify creator
+    if accounts.collection_pda.creators.iter().any(|c| c.address == accounts.mint_authority.key()) {
+        let verify_creator_ix = VerifyBuilder::new()
+            .authority(accounts.mint_authority.key())
+            .metadata(accounts.metadata.key())
+            .build(VerificationArgs::CreatorV1)
+            .map_err(|_| error!(MarketplaceError::MissingProgrammableAccount))?
+            .instruction();
 
-    pub associated_token_program: Program<'info, associated_token::AssociatedToken>,
+        invoke(
+            &verify_creator_ix,
+            &[
+                accounts.mint_authority.to_account_info(),
+                accounts.metadata.to_account_info(),
+                accounts.system_program.to_account_info(),
+                sysvar_instructions.clone(),
+                accounts.token_metadata_program.to_account_info(),
+            ],
+        )?;
+    }
 
-    /// CHECK:
-    pub token_metadata_program: UncheckedAccount<'info>,
+    // Verify collection, signed by collection_pda as update authority of the collection
+    let verify_collection_ix = VerifyBuilder::new()
+        .authority(accounts.collection_pda.key())
+        .metadata(accounts.metadata.key())
+        .collection_mint(accounts.collection_token_mint.key())
+        .collection_metadata(accounts.collection_metadata.key())
+        .collection_master_edition(accounts.collection_master_ed.key())
+        .build(VerificationArgs::CollectionV1)
+        .map_err(|_| error!(MarketplaceError::MissingProgrammableAccount))?
+        .instruction();
 
-    /// CHECK:
-    #[account(mut)]
-    pub master_edition: UncheckedAccount<'info>,
+    invoke_signed(
+        &verify_collection_ix,
+        &[
+            accounts.collection_pda.to_account_info(),
+            accounts.metadata.to_account_info(),
+            accounts.collection_token_mint.to_account_info(),
+            accounts.collection_metadata.to_account_info(),
+            accounts.collection_master_ed.to_account_info(),
+            accounts.system_program.to_account_info(),
+            sysvar_instructions,
+            accounts.token_metadata_program.to_account_info(),
+        ],
+        &[signer_seeds],
+    )?;
 
-    /// CHECK:
-    #[account(mut)]
-    pub metadata: UncheckedAccount<'info>,
+    Ok(())
+}
 
-    /// CHECK:
-    #[account(mut)]
-    collection_authority_record: UncheckedAccount<'info>,
+/// Moves the NFT into the listing escrow, or leaves it in the seller wallet delegated to listing_pda,
+/// and stores the listing data.
+fn list(ctx: Context<ListNft>, price: u64, kind: ListingKind, custody: ListingCustody) -> Result<()> {
+    require!(!ctx.accounts.marketplace.paused, MarketplaceError::MarketplacePaused);
+    require!(price > 0, MarketplaceError::InvalidPrice);
 
-    #[account(
-        init,
-        payer = mint_authority,
-        space = CollectionAccount::get_space(
-            collection_name,
-            collection_symbol,
-            ipfs_image_hash
+    let payment_mint = ctx.accounts.payment_mint.as_ref().map(|m| m.key());
+    assert_payment_mint(&ctx.accounts.marketplace, &payment_mint)?;
+
+    // pNFTs can only be listed through a sale delegate, and only escrow listings take an escrow
+    require!(
+        (custody == ListingCustody::SaleDelegate) == ctx.accounts.collection_pda.programmable,
+        MarketplaceError::WrongListingCustody
+    );
+    require!(
+        (custody == ListingCustody::Escrow) == ctx.accounts.escrow_token_account.is_some(),
+        MarketplaceError::WrongListingCustody
+    );
+
+    let escrow_bump = if custody == ListingCustody::SaleDelegate {
+        let metadata = programmable_account(&ctx.accounts.metadata)?;
+        let edition = programmable_account(&ctx.accounts.edition)?;
+        let token_record = programmable_account(&ctx.accounts.owner_token_record)?;
+        let sysvar_instructions = programmable_account(&ctx.accounts.sysvar_instructions)?;
+        let token_metadata_program = programmable_account(&ctx.accounts.token_metadata_program)?;
+
+        // Make listing_pda the sale delegate, Metaplex locks the pNFT in the seller wallet
+        let mut delegate_builder = DelegateBuilder::new();
+        delegate_builder
+            .delegate(ctx.accounts.listing_pda.key())
+            .metadata(metadata.key())
+            .master_edition(edition.key())
+            .token_record(token_record.key())
+            .mint(ctx.accounts.mint.key())
+            .token(ctx.accounts.seller_token_account.key())
+            .authority(ctx.accounts.seller.key())
+            .payer(ctx.accounts.seller.key())
+            .spl_token_program(ctx.accounts.token_program.key());
+        if let Some(authorization_rules) = &ctx.accounts.authorization_rules {
+            delegate_builder
+                .authorization_rules_program(programmable_account(&ctx.accounts.authorization_rules_program)?.key())
+                .authorization_rules(authorization_rules.key());
+        }
+        let delegate_ix = delegate_builder
+            .build(DelegateArgs::SaleV1 {
+                amount: 1,
+                authorization_data: None,
+            })
+            .map_err(|_| error!(MarketplaceError::MissingProgrammableAccount))?
+            .instruction();
+
+        let mut delegate_accounts = vec![
+            ctx.accounts.listing_pda.to_account_info(),
+            metadata,
+            edition,
+            token_record,
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.seller_token_account.to_account_info(),
+            ctx.accounts.seller.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            sysvar_instructions,
+            ctx.accounts.token_program.to_account_info(),
+            token_metadata_program,
+        ];
+        if let Some(authorization_rules) = &ctx.accounts.authorization_rules {
+            delegate_accounts.push(programmable_account(&ctx.accounts.authorization_rules_program)?);
+            delegate_accounts.push(authorization_rules.to_account_info());
+        }
+
+        invoke(&delegate_ix, &delegate_accounts)?;
+
+        0
+    } else if custody == ListingCustody::Frozen {
+        let edition = programmable_account(&ctx.accounts.edition)?;
+        let token_metadata_program = programmable_account(&ctx.accounts.token_metadata_program)?;
+
+        let nft_pda = ctx.accounts.nft_pda.key();
+        let listing_bump = *ctx.bumps.get("listing_pda").ok_or(MarketplaceError::MissingBump)?;
+        let signer_seeds = [
+            b"listing".as_ref(),
+            nft_pda.as_ref(),
+            &[listing_bump],
+        ];
+
+        // Approve listing_pda as delegate of the NFT
+        token_interface::approve(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::Approve {
+                    to: ctx.accounts.seller_token_account.to_account_info(),
+                    delegate: ctx.accounts.listing_pda.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+
+        // Freeze the seller token account so the NFT can't move until it's sold or delisted
+        invoke_signed(
+            &freeze_delegated_account(
+                token_metadata_program.key(),
+                ctx.accounts.listing_pda.key(),
+                ctx.accounts.seller_token_account.key(),
+                edition.key(),
+                ctx.accounts.mint.key(),
+            ),
+            &[
+                ctx.accounts.listing_pda.to_account_info(),
+                ctx.accounts.seller_token_account.to_account_info(),
+                edition,
+                ctx.accounts.mint.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                token_metadata_program,
+            ],
+            &[&signer_seeds],
+        )?;
+
+        0
+    } else {
+        let escrow_token_account = ctx.accounts.escrow_token_account
+            .as_ref()
+            .ok_or(MarketplaceError::MissingEscrowAccount)?;
+
+        // Move the NFT from the seller into the escrow owned by listing_pda
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::TransferChecked {
+                    from: ctx.accounts.seller_token_account.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: escrow_token_account.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                },
+            ),
+            1,
+            0,
+        )?;
+
+        *ctx.bumps.get("escrow_token_account").ok_or(MarketplaceError::MissingBump)?
+    };
+
+    // Set listing data
+    let clock: Clock = Clock::get()?;
+
+    ctx.accounts.listing_pda.seller = ctx.accounts.seller.key();
+    ctx.accounts.listing_pda.token_mint = ctx.accounts.mint.key();
+    ctx.accounts.listing_pda.price = price;
+    ctx.accounts.listing_pda.kind = kind;
+    ctx.accounts.listing_pda.payment_mint = payment_mint;
+    ctx.accounts.listing_pda.custody = custody;
+    ctx.accounts.listing_pda.bump = *ctx.bumps.get("listing_pda").ok_or(MarketplaceError::MissingBump)?;
+    ctx.accounts.listing_pda.escrow_bump = escrow_bump;
+    ctx.accounts.listing_pda.created = clock.unix_timestamp;
+
+    Ok(())
+}
+
+/// Changes the name and URI of a collection or NFT, signing as collection_pda, the update authority.
+/// Everything else is copied from the current metadata.
+fn update_metadata<'info>(
+    metadata: &AccountInfo<'info>,
+    collection_pda: &Account<'info, CollectionAccount>,
+    token_metadata_program: &AccountInfo<'info>,
+    name: String,
+    uri: String,
+) -> Result<()> {
+    let current = Metadata::from_account_info(metadata)?;
+
+    let data = mpl_token_metadata::state::DataV2 {
+        name,
+        symbol: collection_pda.symbol.clone(),
+        uri,
+        collection: current.collection,
+        creators: current.data.creators,
+        seller_fee_basis_points: current.data.seller_fee_basis_points,
+        uses: current.uses,
+    };
+
+    let coll_mint = collection_pda.token_mint;
+    let coll_bump = collection_pda.bump;
+    let signer_seeds = [
+        b"collection".as_ref(),
+        coll_mint.as_ref(),
+        &[coll_bump],
+    ];
+
+    invoke_signed(
+        &update_metadata_accounts_v2(
+            token_metadata_program.key(),
+            metadata.key(),
+            collection_pda.key(),
+            None,
+            Some(data),
+            None,
+            None,
         ),
-        seeds = [
-            b"collection".as_ref(),
-            mint.to_account_info().key.as_ref()
+        &[
+            token_metadata_program.clone(),
+            metadata.clone(),
+            collection_pda.to_account_info(),
         ],
-        bump
-    )]
-    collection_pda: Box<Account<'info, CollectionAccount>>,
+        &[&signer_seeds],
+    )?;
+
+    Ok(())
 }
 
-#[derive(Accounts)]
-#[instruction(
-    nft_name: String,
-    ipfs_image_hash: String,
-    _metadata_uri: String,
-)]
-pub struct MintNftFromCollection<'info> {
-    /// CHECK:
-    #[account(mut)]
-    pub mint: Signer<'info>,
+/// Checks metadata strings against the Metaplex limits before anything is created.
+fn validate_metadata(name: &str, symbol: &str, uri: &str, ipfs_image_hash: &str) -> Result<()> {
+    require!(name.len() <= MAX_NAME_LENGTH, MarketplaceError::NameTooLong);
+    require!(symbol.len() <= MAX_SYMBOL_LENGTH, MarketplaceError::SymbolTooLong);
+    require!(uri.len() <= MAX_URI_LENGTH, MarketplaceError::UriTooLong);
+    require!(is_valid_cid(ipfs_image_hash), MarketplaceError::InvalidIpfsHash);
+    Ok(())
+}
 
-    /// CHECK:
-    #[account(mut)]
-    pub mint_authority: Signer<'info>,
+/// Accepts CIDv0 hashes (base58btc, `Qm...`) and CIDv1 hashes (base32, `b...`).
+fn is_valid_cid(hash: &str) -> bool {
+    const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
 
-    /// CHECK:
-    #[account(mut)]
-    pub payer: AccountInfo<'info>,
+    if hash.len() == CIDV0_LENGTH && hash.starts_with("Qm") {
+        return hash.chars().all(|c| BASE58_ALPHABET.contains(c));
+    }
+    if (CIDV1_MIN_LENGTH..=MAX_IPFS_HASH_LENGTH).contains(&hash.len()) && hash.starts_with('b') {
+        return hash.chars().all(|c| matches!(c, 'a'..='z' | '2'..='7'));
+    }
+    return false;
+}
 
-    pub rent: Sysvar<'info, Rent>,
+/// Checks a Merkle proof built with sorted pairs of keccak hashes.
+fn verify_merkle_proof(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32]) -> bool {
+    let mut computed = leaf;
+    for node in proof {
+        computed = if computed <= *node {
+            keccak::hashv(&[&computed, node]).0
+        } else {
+            keccak::hashv(&[node, &computed]).0
+        };
+    }
+    computed == root
+}
 
-    pub system_program: Program<'info, System>,
+/// Returns `basis_points` / 10000 of `amount`.
+fn basis_points_of(amount: u64, basis_points: u16) -> Result<u64> {
+    let result = (amount as u128)
+        .checked_mul(basis_points as u128)
+        .ok_or(MarketplaceError::MathOverflow)?
+        / MAX_BASIS_POINTS as u128;
+    return Ok(result as u64);
+}
 
-    pub token_program: Program<'info, Token>,
+/// Moves lamports from a wallet through the system program, or straight out of
+/// an escrow PDA when `from` is owned by this program.
+fn transfer_lamports<'info>(
+    from: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    if from.owner == &crate::ID {
+        let from_lamports = from.lamports().checked_sub(amount).ok_or(MarketplaceError::InsufficientFunds)?;
+        let to_lamports = to.lamports().checked_add(amount).ok_or(MarketplaceError::MathOverflow)?;
+        **from.try_borrow_mut_lamports()? = from_lamports;
+        **to.try_borrow_mut_lamports()? = to_lamports;
+        return Ok(());
+    }
+    system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: from.clone(),
+                to: to.clone(),
+            },
+        ),
+        amount,
+    )
+}
 
-    /// CHECK:
-    #[account(mut)]
-    pub token_account: UncheckedAccount<'info>,
+/// Fails unless the Metaplex metadata belongs to a verified member of `collection_mint`.
+fn assert_verified_collection(metadata: &AccountInfo, collection_mint: &Pubkey) -> Result<()> {
+    let metadata = Metadata::from_account_info(metadata)?;
+    match metadata.collection {
+        Some(collection) if collection.verified && collection.key == *collection_mint => Ok(()),
+        _ => err!(MarketplaceError::CollectionNotVerified),
+    }
+}
 
-    pub associated_token_program: Program<'info, associated_token::AssociatedToken>,
+/// Source of the funds of a sale. Lamports move out of wallets and escrow PDAs,
+/// SPL tokens out of a token account owned by `authority`.
+struct Payment<'a, 'info> {
+    payment_mint: Option<&'a InterfaceAccount<'info, Mint>>,
+    from: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    signer_seeds: &'a [&'a [&'a [u8]]],
+    system_program: &'a Program<'info, System>,
+    token_program: &'a Interface<'info, TokenInterface>,
+}
 
-    /// CHECK:
-    pub token_metadata_program: UncheckedAccount<'info>,
+impl<'a, 'info> Payment<'a, 'info> {
+    /// Pays `amount` to `wallet`. `to` is the wallet itself when paying lamports,
+    /// or a token account of `wallet` in the payment mint when paying SPL tokens.
+    fn pay(&self, to: &AccountInfo<'info>, wallet: &Pubkey, amount: u64) -> Result<()> {
+        let payment_mint = match self.payment_mint {
+            Some(payment_mint) => payment_mint,
+            None => {
+                require_keys_eq!(to.key(), *wallet, MarketplaceError::WrongPaymentAccount);
+                return transfer_lamports(&self.from, to, self.system_program, amount);
+            }
+        };
 
-    #[account(
-        init,
-        payer = mint_authority,
-        space = NftAccount::get_space(
-            nft_name,
-            ipfs_image_hash
-        ),
-        seeds = [
-            b"nft".as_ref(),
-            collection_pda.to_account_info().key.as_ref(),
-            mint.to_account_info().key.as_ref()
-        ],
-        bump
-    )]
-    nft_pda: Box<Account<'info, NftAccount>>,
+        require_keys_eq!(*to.owner, self.token_program.key(), MarketplaceError::WrongPaymentAccount);
+        let token_account = TokenAccount::try_deserialize(&mut &to.try_borrow_data()?[..])?;
+        require!(
+            token_account.mint == payment_mint.key() && token_account.owner == *wallet,
+            MarketplaceError::WrongPaymentAccount
+        );
+        if amount == 0 {
+            return Ok(());
+        }
 
-    /// CHECK:
-    #[account(mut)]
-    pub master_edition: UncheckedAccount<'info>,
+        // Move the tokens, the token program checks the mint and its decimals
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                token_interface::TransferChecked {
+                    from: self.from.clone(),
+                    mint: payment_mint.to_account_info(),
+                    to: to.clone(),
+                    authority: self.authority.clone(),
+                },
+                self.signer_seeds,
+            ),
+            amount,
+            payment_mint.decimals,
+        )
+    }
+}
 
-    /// CHECK:
-    #[account(mut)]
-    pub metadata: UncheckedAccount<'info>,
+/// Pays every verified creator of the NFT its share of the royalties and returns the total paid.
+/// `creator_accounts` must follow the order of the creators in the Metaplex metadata.
+fn pay_royalties<'info>(
+    payment: &Payment<'_, 'info>,
+    metadata: &AccountInfo<'info>,
+    creator_accounts: &[AccountInfo<'info>],
+    price: u64,
+) -> Result<u64> {
+    let metadata = Metadata::from_account_info(metadata)?;
+    let creators = match metadata.data.creators {
+        Some(creators) => creators,
+        None => return Ok(0),
+    };
+    require!(creator_accounts.len() >= creators.len(), MarketplaceError::CreatorAccountsMismatch);
 
-    /// CHECK:
-    #[account(mut)]
-    pub collection_token_mint: UncheckedAccount<'info>,
-    
-    #[account(
-        mut,
-        seeds = [
-            b"collection".as_ref(),
-            collection_token_mint.to_account_info().key.as_ref()
-        ],
-        bump = collection_pda.bump
-    )]
-    collection_pda: Box<Account<'info, CollectionAccount>>,
-    
-    /// CHECK:
-    #[account(mut)]
-    pub collection_metadata: UncheckedAccount<'info>,
+    let royalties = basis_points_of(price, metadata.data.seller_fee_basis_points)?;
 
-    /// CHECK:
-    #[account(mut)]
-    pub collection_master_ed: UncheckedAccount<'info>,
+    let mut paid: u64 = 0;
+    for (creator, creator_account) in creators.iter().zip(creator_accounts.iter()) {
+        if !creator.verified {
+            continue;
+        }
+        let amount = basis_points_of(royalties, creator.share as u16 * 100)?;
+        payment.pay(creator_account, &creator.address, amount)?;
+        paid += amount;
+    }
 
-    /// CHECK: account checked in CPI
-    #[account(mut)]
-    collection_authority_record: UncheckedAccount<'info>,
+    Ok(paid)
 }
 
-#[account]
-#[derive(Default)]
-pub struct CollectionAccount {
-    pub owner: Pubkey,
-    pub token_mint: Pubkey,
-    pub name: String,
-    pub symbol: String,
-    pub ipfs_image_hash: String,
-    pub bump: u8,
-    pub created: i64,
+/// Splits the price of a sale: the marketplace fee goes to the treasury first,
+/// then the royalties to the creators and whatever is left to the seller.
+#[allow(clippy::too_many_arguments)]
+fn settle_sale<'info>(
+    payment: &Payment<'_, 'info>,
+    marketplace: &MarketplaceAccount,
+    seller: &AccountInfo<'info>,
+    seller_wallet: &Pubkey,
+    treasury: &AccountInfo<'info>,
+    treasury_wallet: &Pubkey,
+    metadata: &AccountInfo<'info>,
+    creator_accounts: &[AccountInfo<'info>],
+    price: u64,
+) -> Result<()> {
+    let fee = basis_points_of(price, marketplace.fee_basis_points)?;
+    payment.pay(treasury, treasury_wallet, fee)?;
+
+    let royalties = pay_royalties(payment, metadata, creator_accounts, price)?;
+
+    let proceeds = price
+        .checked_sub(fee)
+        .and_then(|p| p.checked_sub(royalties))
+        .ok_or(MarketplaceError::MathOverflow)?;
+    payment.pay(seller, seller_wallet, proceeds)
 }
 
-impl CollectionAccount {
-    fn get_space(
-        name: String,
-        symbol: String,
-        ipfs_image_hash: String
-    ) -> usize {
-        return DISCRIMINATOR_LENGTH
-            + PUBLIC_KEY_LENGTH
-            + PUBLIC_KEY_LENGTH
-            + Self::get_string_size(name)
-            + Self::get_string_size(symbol)
-            + Self::get_string_size(ipfs_image_hash)
-            + U8_LENGTH
-            + I64_LENGTH;
+/// Fails unless the marketplace accepts `payment_mint`. Lamports are always accepted.
+fn assert_payment_mint(marketplace: &MarketplaceAccount, payment_mint: &Option<Pubkey>) -> Result<()> {
+    if let Some(payment_mint) = payment_mint {
+        require!(
+            marketplace.payment_mints.contains(payment_mint),
+            MarketplaceError::PaymentMintNotAccepted
+        );
     }
-    fn get_string_size(property: String) -> usize {
-        return property.as_bytes().len() + STRING_PREFIX_LENGTH;
+    Ok(())
+}
+
+/// Thaws a NFT listed in the seller wallet, signing as listing_pda, the delegate that froze it.
+fn thaw_listed_nft<'info>(
+    listing_pda: &Account<'info, ListingAccount>,
+    seller_token_account: &AccountInfo<'info>,
+    edition: &AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    token_program: &Interface<'info, TokenInterface>,
+    token_metadata_program: &AccountInfo<'info>,
+    signer_seeds: &[&[u8]],
+) -> Result<()> {
+    invoke_signed(
+        &thaw_delegated_account(
+            token_metadata_program.key(),
+            listing_pda.key(),
+            seller_token_account.key(),
+            edition.key(),
+            mint.key(),
+        ),
+        &[
+            listing_pda.to_account_info(),
+            seller_token_account.clone(),
+            edition.clone(),
+            mint.to_account_info(),
+            token_program.to_account_info(),
+            token_metadata_program.clone(),
+        ],
+        &[signer_seeds],
+    )?;
+
+    Ok(())
+}
+
+/// Accounts of a Metaplex Transfer of a pNFT. Metaplex thaws `token`, moves the pNFT to
+/// `destination`, freezes it there and replaces the owner token record with the destination one.
+struct ProgrammableTransfer<'info> {
+    token: AccountInfo<'info>,
+    token_owner: AccountInfo<'info>,
+    destination: AccountInfo<'info>,
+    destination_owner: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    metadata: AccountInfo<'info>,
+    edition: AccountInfo<'info>,
+    owner_token_record: AccountInfo<'info>,
+    destination_token_record: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    payer: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    sysvar_instructions: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    associated_token_program: AccountInfo<'info>,
+    token_metadata_program: AccountInfo<'info>,
+    authorization_rules: Option<(AccountInfo<'info>, AccountInfo<'info>)>,
+}
+
+impl<'info> ProgrammableTransfer<'info> {
+    /// Transfers the pNFT, `signer_seeds` sign for an authority that is a PDA of this program.
+    fn invoke(&self, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        let mut transfer_builder = TransferBuilder::new();
+        transfer_builder
+            .token(self.token.key())
+            .token_owner(self.token_owner.key())
+            .destination(self.destination.key())
+            .destination_owner(self.destination_owner.key())
+            .mint(self.mint.key())
+            .metadata(self.metadata.key())
+            .edition(self.edition.key())
+            .owner_token_record(self.owner_token_record.key())
+            .destination_token_record(self.destination_token_record.key())
+            .authority(self.authority.key())
+            .payer(self.payer.key())
+            .spl_token_program(self.token_program.key());
+        if let Some((authorization_rules_program, authorization_rules)) = &self.authorization_rules {
+            transfer_builder
+                .authorization_rules_program(authorization_rules_program.key())
+                .authorization_rules(authorization_rules.key());
+        }
+        let transfer_ix = transfer_builder
+            .build(TransferArgs::V1 {
+                amount: 1,
+                authorization_data: None,
+            })
+            .map_err(|_| error!(MarketplaceError::MetadataInstructionFailed))?
+            .instruction();
+
+        let mut transfer_accounts = vec![
+            self.token.clone(),
+            self.token_owner.clone(),
+            self.destination.clone(),
+            self.destination_owner.clone(),
+            self.mint.clone(),
+            self.metadata.clone(),
+            self.edition.clone(),
+            self.owner_token_record.clone(),
+            self.destination_token_record.clone(),
+            self.authority.clone(),
+            self.payer.clone(),
+            self.system_program.clone(),
+            self.sysvar_instructions.clone(),
+            self.token_program.clone(),
+            self.associated_token_program.clone(),
+            self.token_metadata_program.clone(),
+        ];
+        if let Some((authorization_rules_program, authorization_rules)) = &self.authorization_rules {
+            transfer_accounts.push(authorization_rules_program.clone());
+            transfer_accounts.push(authorization_rules.clone());
+        }
+
+        invoke_signed(&transfer_ix, &transfer_accounts, signer_seeds)?;
+
+        Ok(())
     }
 }
 
-#[account]
-#[derive(Default)]
-pub struct NftAccount {
-    pub token_mint: Pubkey,
-    pub collection_mint: Pubkey,
-    pub name: String,
-    pub ipfs_image_hash: String,
-    pub created: i64,
+/// Accounts of a Metaplex Update of pNFT metadata, the legacy metadata update rejects pNFTs.
+/// collection_pda signs as the update authority.
+struct ProgrammableUpdate<'a, 'info> {
+    collection_pda: &'a Account<'info, CollectionAccount>,
+    payer: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    sysvar_instructions: AccountInfo<'info>,
+    token_metadata_program: AccountInfo<'info>,
+    authorization_rules: Option<(AccountInfo<'info>, AccountInfo<'info>)>,
 }
 
-impl NftAccount {
-    fn get_space(
-        name: String,
-        ipfs_image_hash: String
-    ) -> usize {
-        return DISCRIMINATOR_LENGTH
-            + PUBLIC_KEY_LENGTH
-            + PUBLIC_KEY_LENGTH
-            + PUBLIC_KEY_LENGTH
-            + Self::get_string_size(name)
-            + Self::get_string_size(ipfs_image_hash)
-            + I64_LENGTH;
+impl<'a, 'info> ProgrammableUpdate<'a, 'info> {
+    /// Applies `args` to the metadata of `mint`.
+    fn invoke(&self, metadata: &AccountInfo<'info>, mint: &AccountInfo<'info>, args: UpdateArgs) -> Result<()> {
+        let mut update_builder = UpdateBuilder::new();
+        update_builder
+            .authority(self.collection_pda.key())
+            .mint(mint.key())
+            .metadata(metadata.key())
+            .payer(self.payer.key());
+        if let Some((authorization_rules_program, authorization_rules)) = &self.authorization_rules {
+            update_builder
+                .authorization_rules_program(authorization_rules_program.key())
+                .authorization_rules(authorization_rules.key());
+        }
+        let update_ix = update_builder
+            .build(args)
+            .map_err(|_| error!(MarketplaceError::MetadataInstructionFailed))?
+            .instruction();
+
+        let mut update_accounts = vec![
+            self.collection_pda.to_account_info(),
+            mint.clone(),
+            metadata.clone(),
+            self.payer.clone(),
+            self.system_program.clone(),
+            self.sysvar_instructions.clone(),
+            self.token_metadata_program.clone(),
+        ];
+        if let Some((authorization_rules_program, authorization_rules)) = &self.authorization_rules {
+            update_accounts.push(authorization_rules_program.clone());
+            update_accounts.push(authorization_rules.clone());
+        }
+
+        let coll_mint = self.collection_pda.token_mint;
+        let coll_bump = self.collection_pda.bump;
+        let signer_seeds = [
+            b"collection".as_ref(),
+            coll_mint.as_ref(),
+            &[coll_bump],
+        ];
+
+        invoke_signed(&update_ix, &update_accounts, &[&signer_seeds])?;
+
+        Ok(())
     }
-    fn get_string_size(property: String) -> usize {
-        return property.as_bytes().len() + STRING_PREFIX_LENGTH;
+}
+
+/// Update arguments that only change the fields passed, as the update authority.
+fn programmable_update_args(new_update_authority: Option<Pubkey>, data: Option<Data>) -> UpdateArgs {
+    UpdateArgs::AsUpdateAuthorityV2 {
+        new_update_authority,
+        data,
+        primary_sale_happened: None,
+        is_mutable: None,
+        collection: CollectionToggle::None,
+        collection_details: CollectionDetailsToggle::None,
+        uses: UsesToggle::None,
+        rule_set: RuleSetToggle::None,
+        token_standard: None,
+        authorization_data: None,
+    }
+}
+
+/// Pairs the rule set passed for a pNFT with the program that evaluates it, if any was passed.
+fn authorization_rules_accounts<'info>(
+    authorization_rules_program: &Option<UncheckedAccount<'info>>,
+    authorization_rules: &Option<UncheckedAccount<'info>>,
+) -> Result<Option<(AccountInfo<'info>, AccountInfo<'info>)>> {
+    match authorization_rules {
+        Some(authorization_rules) => Ok(Some((
+            programmable_account(authorization_rules_program)?,
+            authorization_rules.to_account_info(),
+        ))),
+        None => Ok(None),
     }
 }
+
+/// Unwraps an optional account that the payment mint makes mandatory.
+fn payment_account<'info, T: ToAccountInfo<'info>>(account: &Option<T>) -> Result<AccountInfo<'info>> {
+    account
+        .as_ref()
+        .map(|a| a.to_account_info())
+        .ok_or_else(|| error!(MarketplaceError::MissingPaymentAccount))
+}
+
+/// Unwraps the mint account of a payment in `payment_mint`, lamport payments have none.
+fn payment_mint_account<'a, 'info>(
+    payment_mint: Option<Pubkey>,
+    account: &'a Option<InterfaceAccount<'info, Mint>>,
+) -> Result<Option<&'a InterfaceAccount<'info, Mint>>> {
+    let payment_mint = match payment_mint {
+        Some(payment_mint) => payment_mint,
+        None => return Ok(None),
+    };
+    let account = account.as_ref().ok_or(MarketplaceError::MissingPaymentAccount)?;
+    require_keys_eq!(account.key(), payment_mint, MarketplaceError::WrongPaymentMint);
+    Ok(Some(account))
+}
+
+/// Unwraps an optional account that programmable NFTs make mandatory.
+fn programmable_account<'info, T: ToAccountInfo<'info>>(account: &Option<T>) -> Result<AccountInfo<'info>> {
+    account
+        .as_ref()
+        .map(|a| a.to_account_info())
+        .ok_or_else(|| error!(MarketplaceError::MissingProgrammableAccount))
+}
+
+#[error_code]
+pub enum MarketplaceError {
+    #[msg("Price must be greater than zero")]
+    InvalidPrice,
+    #[msg("The listing price does not match the expected price")]
+    PriceMismatch,
+    #[msg("The listing seller does not match the expected seller")]
+    SellerMismatch,
+    #[msg("Basis points must not exceed 10000")]
+    InvalidBasisPoints,
+    #[msg("A collection needs between 1 and 5 creators")]
+    InvalidCreators,
+    #[msg("Creator shares must add up to 100")]
+    InvalidCreatorShares,
+    #[msg("Creator accounts do not match the NFT metadata creators")]
+    CreatorAccountsMismatch,
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
+    #[msg("The marketplace is paused")]
+    MarketplacePaused,
+    #[msg("Insufficient funds")]
+    InsufficientFunds,
+    #[msg("Expiry must be in the future")]
+    InvalidExpiry,
+    #[msg("The offer has expired")]
+    OfferExpired,
+    #[msg("The offer has not expired yet")]
+    OfferNotExpired,
+    #[msg("Quantity must be greater than zero")]
+    InvalidQuantity,
+    #[msg("The NFT does not belong to this collection")]
+    WrongCollection,
+    #[msg("The NFT is not verified in the collection")]
+    CollectionNotVerified,
+    #[msg("Auction extension must be between 0 seconds and one day")]
+    InvalidAuctionExtension,
+    #[msg("The auction has ended")]
+    AuctionEnded,
+    #[msg("The auction has not ended yet")]
+    AuctionNotEnded,
+    #[msg("The auction already has bids")]
+    AuctionHasBids,
+    #[msg("The bid is below the reserve price or the minimum increment")]
+    BidTooLow,
+    #[msg("The bidder does not match the auction")]
+    WrongBidder,
+    #[msg("The listing price is above the maximum price")]
+    PriceAboveMaximum,
+    #[msg("Dutch auctions need a start price above a non-zero floor price and an end time after the start time")]
+    InvalidDutchAuction,
+    #[msg("Mint end time must be after its start time")]
+    InvalidMintWindow,
+    #[msg("Max supply is below the number of NFTs already minted")]
+    InvalidMaxSupply,
+    #[msg("The collection has no mint phase with this index")]
+    InvalidMintPhase,
+    #[msg("The mint is not active")]
+    MintNotActive,
+    #[msg("The collection supply is exhausted")]
+    SupplyExhausted,
+    #[msg("The account is not the collection owner")]
+    WrongCollectionOwner,
+    #[msg("A collection can have at most 5 mint phases")]
+    TooManyMintPhases,
+    #[msg("The wallet is not on the allowlist of this mint phase")]
+    NotOnAllowlist,
+    #[msg("The wallet reached its mint limit for this phase")]
+    WalletLimitReached,
+    #[msg("The payment account does not belong to the expected wallet and payment mint")]
+    WrongPaymentAccount,
+    #[msg("The marketplace does not accept this payment mint")]
+    PaymentMintNotAccepted,
+    #[msg("An account needed to pay with SPL tokens is missing")]
+    MissingPaymentAccount,
+    #[msg("Too many payment mints")]
+    TooManyPaymentMints,
+    #[msg("The token metadata program is not the Metaplex Token Metadata program")]
+    WrongTokenMetadataProgram,
+    #[msg("The metadata account is not the Metaplex metadata PDA of the mint")]
+    WrongMetadataAccount,
+    #[msg("The master edition account is not the Metaplex master edition PDA of the mint")]
+    WrongMasterEditionAccount,
+    #[msg("The collection authority record is not the Metaplex PDA of the collection")]
+    WrongCollectionAuthorityRecord,
+    #[msg("The token account is not the associated token account of the mint authority")]
+    WrongTokenAccount,
+    #[msg("The signer is not allowed to perform this action")]
+    Unauthorized,
+    #[msg("The bump of a program derived address is missing")]
+    MissingBump,
+    #[msg("The name is longer than 32 bytes")]
+    NameTooLong,
+    #[msg("The symbol is longer than 10 bytes")]
+    SymbolTooLong,
+    #[msg("The metadata URI is longer than 200 bytes")]
+    UriTooLong,
+    #[msg("The IPFS image hash is not a valid CIDv0 or CIDv1")]
+    InvalidIpfsHash,
+    #[msg("The signer is not the proposed owner of the collection")]
+    NotPendingOwner,
+    #[msg("The collection is locked and its metadata can no longer change")]
+    CollectionLocked,
+    #[msg("The signer does not hold the NFT")]
+    NotNftHolder,
+    #[msg("The edition marker is not the Metaplex PDA of the next edition")]
+    WrongEditionMarker,
+    #[msg("Only programmable collections can have a rule set")]
+    InvalidRuleSet,
+    #[msg("Programmable NFTs are not supported by this instruction")]
+    ProgrammableNotSupported,
+    #[msg("An account required for programmable NFTs or frozen listings is missing")]
+    MissingProgrammableAccount,
+    #[msg("The listing escrow token account is missing")]
+    MissingEscrowAccount,
+    #[msg("The token record is not the Metaplex PDA of the token account")]
+    WrongTokenRecordAccount,
+    #[msg("The signer is not a creator of the collection")]
+    NotCreator,
+    #[msg("Only the collection owner or its minters can mint into the collection")]
+    NotAllowedToMint,
+    #[msg("Token Metadata only supports SPL Token mints for collections, NFTs and editions")]
+    MetadataRequiresSplToken,
+    #[msg("The listing custody doesn't match the NFT or the escrow account passed")]
+    WrongListingCustody,
+    #[msg("The update authority of the collection was released, its metadata can't be changed anymore")]
+    UpdateAuthorityReleased,
+    #[msg("The Token Metadata instruction could not be built")]
+    MetadataInstructionFailed,
+    #[msg("SPL token auctions need their bid escrow to be closed")]
+    MissingBidEscrow,
+    #[msg("The payment mint account is not the mint of the payment")]
+    WrongPaymentMint,
+}
@@ -1,8 +1,9 @@
-import * as anchor from '@project-serum/anchor';
-import { Program, BN } from '@project-serum/anchor';
-import { PublicKey, Keypair, SystemProgram, SYSVAR_RENT_PUBKEY, Transaction, ComputeBudgetProgram } from '@solana/web3.js';
-import { getAssociatedTokenAddressSync, TOKEN_PROGRAM_ID } from '@solana/spl-token';
+import * as anchor from '@coral-xyz/anchor';
+import { Program, BN } from '@coral-xyz/anchor';
+import { PublicKey, Keypair, SystemProgram, SYSVAR_RENT_PUBKEY, SYSVAR_INSTRUCTIONS_PUBKEY, Transaction, ComputeBudgetProgram } from '@solana/web3.js';
+import { createMint, getAccount, getAssociatedTokenAddressSync, getOrCreateAssociatedTokenAccount, mintTo, transfer, TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID } from '@solana/spl-token';
 import { Metaplex } from '@metaplex-foundation/js'
+import { keccak_256 } from '@noble/hashes/sha3';
 import { SolanaNft } from '../target/types/solana_nft';
 import { expect } from 'chai';
 
@@ -15,6 +16,10 @@ const SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID = new PublicKey(
   'ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL'
 );
 
+const BPF_UPGRADEABLE_LOADER_PROGRAM_ID = new PublicKey(
+  'BPFLoaderUpgradeab1e11111111111111111111111'
+);
+
 const program = anchor.workspace.SolanaNft as Program<SolanaNft>;
 
 describe('Solana NFTs', () => {
@@ -79,8 +84,11 @@ describe('Solana NFTs', () => {
       .mintCollection(
         'My First Collection',
         'MFC',
-        'l0Vjj3rZKQm-FVbCCj2OH15YMWAveUseuCLGkcPE-x0',                        // IPFS Image Hash
+        'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG',                     // IPFS Image Hash
         'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',    // Metadata URI
+        500,                                                                  // Royalties (5%)
+        [{ address: provider.wallet.publicKey, share: 100 }],                 // Creators
+        { programmable: false, ruleSet: null },                               // Programmable config
       )
       .accounts({
         mint: collectionKP.publicKey,
@@ -115,6 +123,57 @@ describe('Solana NFTs', () => {
     console.log('tx confirm', con);
   });
 
+  async function expectMintCollectionError(
+    code: string,
+    { name = 'Invalid Collection', symbol = 'INV', ipfsImageHash = 'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG', uri = 'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA', tokenProgram = TOKEN_PROGRAM_ID } = {},
+  ) {
+    const mintKP = Keypair.generate();
+
+    try {
+      await program.methods
+        .mintCollection(
+          name,
+          symbol,
+          ipfsImageHash,
+          uri,
+          500,
+          [{ address: provider.wallet.publicKey, share: 100 }],
+          { programmable: false, ruleSet: null },
+        )
+        .accounts({
+          mint: mintKP.publicKey,
+          mintAuthority: provider.wallet.publicKey,
+          payer: provider.wallet.publicKey,
+          rent: SYSVAR_RENT_PUBKEY,
+          systemProgram: SystemProgram.programId,
+          tokenProgram,
+          tokenAccount: getAssociatedTokenAddressSync(mintKP.publicKey, provider.wallet.publicKey, false, tokenProgram),
+          associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          masterEdition: getMasterEditionPDA(mintKP.publicKey),
+          metadata: getMetadataPDA(mintKP.publicKey),
+          collectionAuthorityRecord: getCollectionAuthorityRecordPDA(mintKP.publicKey, getCollectionPDA(mintKP.publicKey)),
+          collectionPda: getCollectionPDA(mintKP.publicKey),
+        })
+        .signers([mintKP])
+        .rpc();
+      expect.fail(`Minting the collection should fail with ${code}`);
+    } catch (err) {
+      expect(err.error.errorCode.code).equal(code);
+    }
+  }
+
+  it('Mint collection with Token-2022 fails', async () => {
+    await expectMintCollectionError('MetadataRequiresSplToken', { tokenProgram: TOKEN_2022_PROGRAM_ID });
+  });
+
+  it('Mint collection with invalid metadata fails', async () => {
+    await expectMintCollectionError('NameTooLong', { name: 'N'.repeat(33) });
+    await expectMintCollectionError('SymbolTooLong', { symbol: 'S'.repeat(11) });
+    await expectMintCollectionError('UriTooLong', { uri: 'https://arweave.net/' + 'u'.repeat(181) });
+    await expectMintCollectionError('InvalidIpfsHash', { ipfsImageHash: 'QmNotAValidHash' });
+  });
+
   it('Get all collections', async () => {
     const collections = await program.account.collectionAccount.all();
     expect(1).equal(collections.length);
@@ -169,8 +228,10 @@ describe('Solana NFTs', () => {
     const i = await program.methods
       .mintNftFromCollection(
         'First NFT',
-        'l0Vjj3rZKQm-FVbCCj2OH15YMWAveUseuCLGkcPE-x0',                        // IPFS Image Hash
+        'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG',                     // IPFS Image Hash
         'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',    // Metadata URI
+        new BN(10),                                                           // Edition max supply
+        new BN(10_000_000),                                                   // Edition price
       )
       .accounts({
         mint: nftKP.publicKey,
@@ -246,81 +307,2524 @@ describe('Solana NFTs', () => {
     expect(nftKP.publicKey.toString()).equal(nft.address.toString());
   });
 
-});
 
 
-/* ******************************
-       BUILD METAPLEX PDAs
-****************************** */
+  /* ******************************
+             MARKETPLACE
+  ****************************** */
 
-function getMetadataPDA(tokenMint: PublicKey): PublicKey {
-  const [metadataPDA] = anchor.web3.PublicKey
-    .findProgramAddressSync(
-      [
-        Buffer.from('metadata'),
-        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
-        tokenMint.toBuffer(),
-      ],
-      TOKEN_METADATA_PROGRAM_ID
-    );
-  return metadataPDA;
-}
+  const marketplacePDA = getMarketplacePDA();
+  const treasuryPDA = getTreasuryPDA();
 
-function getMasterEditionPDA(tokenMint: PublicKey): PublicKey {
-  const [masterEditionPDA] = anchor.web3.PublicKey
-    .findProgramAddressSync(
-      [
-        Buffer.from('metadata'),
-        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
-        tokenMint.toBuffer(),
-        Buffer.from('edition'),
-      ],
-      TOKEN_METADATA_PROGRAM_ID
-    );
-  return masterEditionPDA;
-}
+  it('Initialize marketplace', async () => {
+    await program.methods
+      .initializeMarketplace(250)           // Fee (2.5%)
+      .accounts({
+        admin: provider.wallet.publicKey,
+        marketplace: marketplacePDA,
+        treasury: treasuryPDA,
+        program: program.programId,
+        programData: getProgramDataPDA(),
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
 
-function getCollectionAuthorityRecordPDA(collectionTokenMint: PublicKey, collectionPDA: PublicKey): PublicKey {
-  const [collectionAuthorityRecordPDA] = anchor.web3.PublicKey
-    .findProgramAddressSync(
-      [
-        Buffer.from('metadata'),
-        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
-        collectionTokenMint.toBuffer(),
-        Buffer.from('collection_authority'),
-        collectionPDA.toBuffer(),
-      ],
-      TOKEN_METADATA_PROGRAM_ID
-    );
-  return collectionAuthorityRecordPDA;
-}
+    const marketplace = await program.account.marketplaceAccount.fetch(marketplacePDA);
+    expect(250).equal(marketplace.feeBasisPoints);
+    expect(false).equal(marketplace.paused);
+  });
 
 
-/* ******************************
-       BUILD CUSTOM PDAs
-****************************** */
 
-function getCollectionPDA(collectionTokenMint: PublicKey): PublicKey {
-  const [collectionPDA] = anchor.web3.PublicKey
-    .findProgramAddressSync(
-      [
-        Buffer.from('collection'),
-        collectionTokenMint.toBuffer(),
-      ],
-      program.programId
-    );
-  return collectionPDA;
-}
+  /* ******************************
+              LISTINGS
+  ****************************** */
 
-function getNftPDA(collectionPDA: PublicKey, nftTokenMint: PublicKey): PublicKey {
-  const [nftPDA] = anchor.web3.PublicKey
-    .findProgramAddressSync(
-      [
-        Buffer.from('nft'),
-        collectionPDA.toBuffer(),
-        nftTokenMint.toBuffer(),
-      ],
-      program.programId
-    );
-  return nftPDA;
+  const listingPDA = getListingPDA(nftPDA);
+  const escrowPDA = getEscrowPDA(nftPDA);
+
+  it('List NFT', async () => {
+    await program.methods
+      .listNft(new BN(1_000_000_000), { escrow: {} })
+      .accounts({
+        seller: provider.wallet.publicKey,
+        mint: nftTokenMint,
+        sellerTokenAccount: nftATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        listingPda: listingPDA,
+        escrowTokenAccount: escrowPDA,
+        marketplace: marketplacePDA,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const listing = await program.account.listingAccount.fetch(listingPDA);
+    expect(provider.wallet.publicKey.toString()).equal(listing.seller.toString());
+    expect(1_000_000_000).equal(listing.price.toNumber());
+
+    const escrow = await getAccount(provider.connection, escrowPDA);
+    expect(1).equal(Number(escrow.amount));
+  });
+
+  it('Delist NFT', async () => {
+    await program.methods
+      .delistNft()
+      .accounts({
+        seller: provider.wallet.publicKey,
+        mint: nftTokenMint,
+        sellerTokenAccount: nftATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        listingPda: listingPDA,
+        escrowTokenAccount: escrowPDA,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const listing = await program.account.listingAccount.fetchNullable(listingPDA);
+    expect(listing).to.be.null;
+
+    const ata = await getAccount(provider.connection, nftATA);
+    expect(1).equal(Number(ata.amount));
+  });
+
+  it('List NFT frozen in the seller wallet and delist it', async () => {
+    const listAccounts = {
+      seller: provider.wallet.publicKey,
+      mint: nftTokenMint,
+      sellerTokenAccount: nftATA,
+      collectionPda: collectionPDA,
+      nftPda: nftPDA,
+      listingPda: listingPDA,
+      escrowTokenAccount: null,
+      marketplace: marketplacePDA,
+      edition: nftMasterEditionPDA,
+      tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      rent: SYSVAR_RENT_PUBKEY,
+      systemProgram: SystemProgram.programId,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    try {
+      await program.methods
+        .listNft(new BN(1_000_000_000), { escrow: {} })
+        .accounts(listAccounts)
+        .rpc();
+      expect.fail('Escrow listings need the escrow account');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('WrongListingCustody');
+    }
+
+    await program.methods
+      .listNft(new BN(1_000_000_000), { frozen: {} })
+      .accounts(listAccounts)
+      .rpc();
+
+    let ata = await getAccount(provider.connection, nftATA);
+    expect(1).equal(Number(ata.amount));
+    expect(true).equal(ata.isFrozen);
+    expect(listingPDA.toString()).equal(ata.delegate.toString());
+
+    await program.methods
+      .delistNft()
+      .accounts({
+        seller: provider.wallet.publicKey,
+        mint: nftTokenMint,
+        sellerTokenAccount: nftATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        listingPda: listingPDA,
+        escrowTokenAccount: null,
+        edition: nftMasterEditionPDA,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    ata = await getAccount(provider.connection, nftATA);
+    expect(false).equal(ata.isFrozen);
+    expect(ata.delegate).to.be.null;
+  });
+
+  const buyerKP = Keypair.generate();
+  const buyerATA = getAssociatedTokenAddressSync(nftTokenMint, buyerKP.publicKey);
+
+  it('Buy NFT', async () => {
+    const price = new BN(1_000_000_000);
+
+    await program.methods
+      .listNft(price, { escrow: {} })
+      .accounts({
+        seller: provider.wallet.publicKey,
+        mint: nftTokenMint,
+        sellerTokenAccount: nftATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        listingPda: listingPDA,
+        escrowTokenAccount: escrowPDA,
+        marketplace: marketplacePDA,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const airdrop = await provider.connection.requestAirdrop(buyerKP.publicKey, 2_000_000_000);
+    await provider.connection.confirmTransaction(airdrop);
+
+    await program.methods
+      .buyNft(price, provider.wallet.publicKey)
+      .accounts({
+        buyer: buyerKP.publicKey,
+        seller: provider.wallet.publicKey,
+        mint: nftTokenMint,
+        buyerTokenAccount: buyerATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        listingPda: listingPDA,
+        escrowTokenAccount: escrowPDA,
+        metadata: nftMetadataPDA,
+        marketplace: marketplacePDA,
+        treasury: treasuryPDA,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: provider.wallet.publicKey, isWritable: true, isSigner: false },   // Creators
+      ])
+      .signers([buyerKP])
+      .rpc();
+
+    const listing = await program.account.listingAccount.fetchNullable(listingPDA);
+    expect(listing).to.be.null;
+
+    const ata = await getAccount(provider.connection, buyerATA);
+    expect(1).equal(Number(ata.amount));
+  });
+
+  it('Withdraw treasury', async () => {
+    const fee = 25_000_000;   // 2.5% of 1 SOL
+    const before = await provider.connection.getBalance(treasuryPDA);
+
+    await program.methods
+      .withdrawTreasury(new BN(fee))
+      .accounts({
+        admin: provider.wallet.publicKey,
+        destination: provider.wallet.publicKey,
+        marketplace: marketplacePDA,
+        treasury: treasuryPDA,
+      })
+      .rpc();
+
+    const after = await provider.connection.getBalance(treasuryPDA);
+    expect(before - fee).equal(after);
+  });
+
+
+
+  /* ******************************
+               OFFERS
+  ****************************** */
+
+  const offerPDA = getOfferPDA(nftPDA, provider.wallet.publicKey);
+
+  async function makeOffer(price: BN, expiry: BN) {
+    await program.methods
+      .makeOffer(price, expiry)
+      .accounts({
+        bidder: provider.wallet.publicKey,
+        mint: nftTokenMint,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        offerPda: offerPDA,
+        marketplace: marketplacePDA,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+  }
+
+  it('Make offer', async () => {
+    const expiry = new BN(Math.floor(Date.now() / 1000) + 3600);
+    await makeOffer(new BN(500_000_000), expiry);
+
+    const offer = await program.account.offerAccount.fetch(offerPDA);
+    expect(provider.wallet.publicKey.toString()).equal(offer.bidder.toString());
+    expect(500_000_000).equal(offer.price.toNumber());
+  });
+
+  it('Cancel offer', async () => {
+    await program.methods
+      .cancelOffer()
+      .accounts({
+        authority: provider.wallet.publicKey,
+        bidder: provider.wallet.publicKey,
+        offerPda: offerPDA,
+        nftPda: nftPDA,
+      })
+      .rpc();
+
+    const offer = await program.account.offerAccount.fetchNullable(offerPDA);
+    expect(offer).to.be.null;
+  });
+
+  function acceptOffer(price: BN) {
+    return program.methods
+      .acceptOffer(price)
+      .accounts({
+        seller: buyerKP.publicKey,
+        bidder: provider.wallet.publicKey,
+        mint: nftTokenMint,
+        sellerTokenAccount: buyerATA,
+        bidderTokenAccount: nftATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        offerPda: offerPDA,
+        metadata: nftMetadataPDA,
+        marketplace: marketplacePDA,
+        treasury: treasuryPDA,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: provider.wallet.publicKey, isWritable: true, isSigner: false },   // Creators
+      ])
+      .signers([buyerKP])
+      .rpc();
+  }
+
+  it('Refund an offer before it expires fails', async () => {
+    await makeOffer(new BN(100_000_000), new BN(Math.floor(Date.now() / 1000) + 5));
+
+    try {
+      await program.methods
+        .cancelOffer()
+        .accounts({
+          authority: buyerKP.publicKey,
+          bidder: provider.wallet.publicKey,
+          offerPda: offerPDA,
+          nftPda: nftPDA,
+        })
+        .signers([buyerKP])
+        .rpc();
+      expect.fail('Only the bidder can cancel an offer that did not expire');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('OfferNotExpired');
+    }
+  });
+
+  it('Accept an expired offer fails', async () => {
+    // Wait for the offer to expire
+    await new Promise(resolve => setTimeout(resolve, 7000));
+
+    try {
+      await acceptOffer(new BN(100_000_000));
+      expect.fail('Expired offers cannot be accepted');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('OfferExpired');
+    }
+  });
+
+  it('Anyone refunds the bidder of an expired offer', async () => {
+    const before = await provider.connection.getBalance(provider.wallet.publicKey);
+    const escrowed = await provider.connection.getBalance(offerPDA);
+
+    // The buyer cranks the refund and pays the fee, so the bidder balance only grows
+    const i = await program.methods
+      .cancelOffer()
+      .accounts({
+        authority: buyerKP.publicKey,
+        bidder: provider.wallet.publicKey,
+        offerPda: offerPDA,
+        nftPda: nftPDA,
+      })
+      .instruction();
+
+    const t = new Transaction().add(i);
+
+    const latestBlockHash = await provider.connection.getLatestBlockhash();
+    t.recentBlockhash = latestBlockHash.blockhash;
+    t.lastValidBlockHeight = latestBlockHash.lastValidBlockHeight;
+
+    t.feePayer = buyerKP.publicKey;
+    t.sign(buyerKP);
+
+    const tx = await provider.connection.sendRawTransaction(t.serialize());
+    await provider.connection.confirmTransaction(tx);
+
+    const offer = await program.account.offerAccount.fetchNullable(offerPDA);
+    expect(offer).to.be.null;
+
+    // The escrowed price and the rent of offer_pda go back to the bidder
+    const after = await provider.connection.getBalance(provider.wallet.publicKey);
+    expect(before + escrowed).equal(after);
+  });
+
+  it('Accept offer', async () => {
+    const price = new BN(500_000_000);
+    const expiry = new BN(Math.floor(Date.now() / 1000) + 3600);
+    await makeOffer(price, expiry);
+
+    await acceptOffer(price);
+
+    const offer = await program.account.offerAccount.fetchNullable(offerPDA);
+    expect(offer).to.be.null;
+
+    const ata = await getAccount(provider.connection, nftATA);
+    expect(1).equal(Number(ata.amount));
+  });
+
+
+
+  /* ******************************
+           COLLECTION BIDS
+  ****************************** */
+
+  const collectionBidPDA = getCollectionBidPDA(collectionPDA, buyerKP.publicKey);
+
+  it('Make collection bid', async () => {
+    await program.methods
+      .makeCollectionBid(new BN(300_000_000), 1)
+      .accounts({
+        bidder: buyerKP.publicKey,
+        collectionPda: collectionPDA,
+        collectionBidPda: collectionBidPDA,
+        marketplace: marketplacePDA,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyerKP])
+      .rpc();
+
+    const bid = await program.account.collectionBidAccount.fetch(collectionBidPDA);
+    expect(collectionTokenMint.toString()).equal(bid.collectionMint.toString());
+    expect(1).equal(bid.quantity);
+  });
+
+  it('Fill collection bid', async () => {
+    await program.methods
+      .fillCollectionBid(new BN(300_000_000))
+      .accounts({
+        seller: provider.wallet.publicKey,
+        bidder: buyerKP.publicKey,
+        mint: nftTokenMint,
+        sellerTokenAccount: nftATA,
+        bidderTokenAccount: buyerATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        collectionBidPda: collectionBidPDA,
+        metadata: nftMetadataPDA,
+        marketplace: marketplacePDA,
+        treasury: treasuryPDA,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: provider.wallet.publicKey, isWritable: true, isSigner: false },   // Creators
+      ])
+      .rpc();
+
+    // A bid with a quantity of 1 is closed once filled
+    const bid = await program.account.collectionBidAccount.fetchNullable(collectionBidPDA);
+    expect(bid).to.be.null;
+
+    const ata = await getAccount(provider.connection, buyerATA);
+    expect(1).equal(Number(ata.amount));
+  });
+
+
+
+  /* ******************************
+              AUCTIONS
+  ****************************** */
+
+  const auctionPDA = getAuctionPDA(nftPDA);
+  const auctionEscrowPDA = getAuctionEscrowPDA(nftPDA);
+
+  async function createAuction(endTime: BN, extensionSeconds = new BN(0)) {
+    await program.methods
+      .createAuction(new BN(100_000_000), new BN(10_000_000), endTime, extensionSeconds)
+      .accounts({
+        seller: buyerKP.publicKey,
+        mint: nftTokenMint,
+        sellerTokenAccount: buyerATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        auctionPda: auctionPDA,
+        escrowTokenAccount: auctionEscrowPDA,
+        marketplace: marketplacePDA,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .signers([buyerKP])
+      .rpc();
+  }
+
+  it('Create auction with a too long extension fails', async () => {
+    try {
+      await createAuction(new BN(Math.floor(Date.now() / 1000) + 3600), new BN(24 * 60 * 60 + 1));
+      expect.fail('Auction extensions are capped to one day');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('InvalidAuctionExtension');
+    }
+  });
+
+  it('Cancel auction', async () => {
+    await createAuction(new BN(Math.floor(Date.now() / 1000) + 3600));
+
+    await program.methods
+      .cancelAuction()
+      .accounts({
+        seller: buyerKP.publicKey,
+        mint: nftTokenMint,
+        sellerTokenAccount: buyerATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        auctionPda: auctionPDA,
+        escrowTokenAccount: auctionEscrowPDA,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .signers([buyerKP])
+      .rpc();
+
+    const auction = await program.account.auctionAccount.fetchNullable(auctionPDA);
+    expect(auction).to.be.null;
+  });
+
+  const bidderKP = Keypair.generate();
+
+  async function placeBid(bidder: Keypair | null, previousBidder: PublicKey, amount: BN) {
+    await program.methods
+      .placeBid(amount)
+      .accounts({
+        bidder: bidder ? bidder.publicKey : provider.wallet.publicKey,
+        previousBidder: previousBidder,
+        auctionPda: auctionPDA,
+        nftPda: nftPDA,
+        marketplace: marketplacePDA,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers(bidder ? [bidder] : [])
+      .rpc();
+  }
+
+  it('Late bids extend the auction', async () => {
+    const airdrop = await provider.connection.requestAirdrop(bidderKP.publicKey, 1_000_000_000);
+    await provider.connection.confirmTransaction(airdrop);
+
+    // The first bid lands in the last 5 seconds of a 10 seconds extension
+    const endTime = new BN(Math.floor(Date.now() / 1000) + 5);
+    await createAuction(endTime, new BN(10));
+
+    await placeBid(bidderKP, bidderKP.publicKey, new BN(100_000_000));
+
+    const auction = await program.account.auctionAccount.fetch(auctionPDA);
+    expect(bidderKP.publicKey.toString()).equal(auction.highestBidder.toString());
+    expect(auction.endTime.toNumber()).greaterThan(endTime.toNumber());
+  });
+
+  it('Outbid bidder is refunded', async () => {
+    const before = await provider.connection.getBalance(bidderKP.publicKey);
+
+    await placeBid(null, bidderKP.publicKey, new BN(110_000_000));
+
+    const auction = await program.account.auctionAccount.fetch(auctionPDA);
+    expect(provider.wallet.publicKey.toString()).equal(auction.highestBidder.toString());
+    expect(110_000_000).equal(auction.highestBid.toNumber());
+
+    const after = await provider.connection.getBalance(bidderKP.publicKey);
+    expect(before + 100_000_000).equal(after);
+  });
+
+  it('Settle auction', async () => {
+    // Wait for the extended auction to end
+    const auction = await program.account.auctionAccount.fetch(auctionPDA);
+    const remaining = auction.endTime.toNumber() - Math.floor(Date.now() / 1000);
+    await new Promise(resolve => setTimeout(resolve, (remaining + 2) * 1000));
+
+    await program.methods
+      .settleAuction()
+      .accounts({
+        payer: provider.wallet.publicKey,
+        seller: buyerKP.publicKey,
+        winner: provider.wallet.publicKey,
+        mint: nftTokenMint,
+        winnerTokenAccount: nftATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        auctionPda: auctionPDA,
+        escrowTokenAccount: auctionEscrowPDA,
+        metadata: nftMetadataPDA,
+        marketplace: marketplacePDA,
+        treasury: treasuryPDA,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: provider.wallet.publicKey, isWritable: true, isSigner: false },   // Creators
+      ])
+      .rpc();
+
+    const settled = await program.account.auctionAccount.fetchNullable(auctionPDA);
+    expect(settled).to.be.null;
+
+    const ata = await getAccount(provider.connection, nftATA);
+    expect(1).equal(Number(ata.amount));
+  });
+
+
+
+  /* ******************************
+           DUTCH AUCTIONS
+  ****************************** */
+
+  it('List NFT as Dutch auction and buy it', async () => {
+    const now = Math.floor(Date.now() / 1000);
+
+    await program.methods
+      .listNftDutch(new BN(400_000_000), new BN(200_000_000), new BN(now), new BN(now + 3600), { escrow: {} })
+      .accounts({
+        seller: provider.wallet.publicKey,
+        mint: nftTokenMint,
+        sellerTokenAccount: nftATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        listingPda: listingPDA,
+        escrowTokenAccount: escrowPDA,
+        marketplace: marketplacePDA,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    const listing = await program.account.listingAccount.fetch(listingPDA);
+    expect(listing.kind.dutch.floorPrice.toNumber()).equal(200_000_000);
+
+    // The price only falls, so the start price is a safe maximum
+    await program.methods
+      .buyNft(new BN(400_000_000), provider.wallet.publicKey)
+      .accounts({
+        buyer: buyerKP.publicKey,
+        seller: provider.wallet.publicKey,
+        mint: nftTokenMint,
+        buyerTokenAccount: buyerATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        listingPda: listingPDA,
+        escrowTokenAccount: escrowPDA,
+        metadata: nftMetadataPDA,
+        marketplace: marketplacePDA,
+        treasury: treasuryPDA,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: provider.wallet.publicKey, isWritable: true, isSigner: false },   // Creators
+      ])
+      .signers([buyerKP])
+      .rpc();
+
+    const ata = await getAccount(provider.connection, buyerATA);
+    expect(1).equal(Number(ata.amount));
+  });
+
+
+
+  /* ******************************
+            PUBLIC MINTS
+  ****************************** */
+
+  it('Configure mint phases', async () => {
+    const now = Math.floor(Date.now() / 1000);
+
+    await program.methods
+      .configureMintPhases(new BN(10), [
+        {
+          price: new BN(100_000_000),
+          startTime: new BN(now - 60),
+          endTime: new BN(now + 3600),
+          perWalletLimit: 1,
+          merkleRoot: null,
+          paymentMint: null,
+        },
+      ])
+      .accounts({
+        owner: provider.wallet.publicKey,
+        collectionPda: collectionPDA,
+        marketplace: marketplacePDA,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const collection = await program.account.collectionAccount.fetch(collectionPDA);
+    expect(10).equal(collection.maxSupply.toNumber());
+    expect(100_000_000).equal(collection.mintPhases[0].price.toNumber());
+    expect(1).equal(collection.phasesVersion);
+  });
+
+  const publicNftKP = Keypair.generate();
+  const publicNftTokenMint = publicNftKP.publicKey;
+
+  it('Public mint NFT', async () => {
+    const t = new Transaction();
+
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 300000 
+    });
+    t.add(modifyComputeUnits);
+
+    const i = await program.methods
+      .publicMintNft(
+        'Public NFT',
+        'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG',                     // IPFS Image Hash
+        'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',    // Metadata URI
+        0,                                                                    // Mint phase
+        [],                                                                   // Merkle proof
+      )
+      .accounts({
+        mintNft: {
+          mint: publicNftTokenMint,
+          mintAuthority: buyerKP.publicKey,
+          payer: buyerKP.publicKey,
+          rent: SYSVAR_RENT_PUBKEY,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenAccount: getAssociatedTokenAddressSync(publicNftTokenMint, buyerKP.publicKey),
+          associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          nftPda: getNftPDA(collectionPDA, publicNftTokenMint),
+          masterEdition: getMasterEditionPDA(publicNftTokenMint),
+          metadata: getMetadataPDA(publicNftTokenMint),
+          collectionTokenMint: collectionKP.publicKey,
+          collectionPda: collectionPDA,
+          collectionMetadata: collectionMetadataPDA,
+          collectionMasterEd: collectionMasterEditionPDA,
+          collectionAuthorityRecord: collectionAuthorityRecordPDA,
+        },
+        collectionOwner: provider.wallet.publicKey,
+        walletMintsPda: getWalletMintsPDA(collectionPDA, buyerKP.publicKey),
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+
+    t.add(i);
+
+    const latestBlockHash = await provider.connection.getLatestBlockhash();
+    t.recentBlockhash = latestBlockHash.blockhash;
+    t.lastValidBlockHeight = latestBlockHash.lastValidBlockHeight;
+
+    t.feePayer = buyerKP.publicKey;
+    t.sign(buyerKP, publicNftKP);
+
+    const tx = await provider.connection.sendRawTransaction(t.serialize());
+    await provider.connection.confirmTransaction(tx);
+
+    const collection = await program.account.collectionAccount.fetch(collectionPDA);
+    expect(2).equal(collection.mintedCount.toNumber());
+
+    const walletMints = await program.account.walletMintsAccount.fetch(
+      getWalletMintsPDA(collectionPDA, buyerKP.publicKey)
+    );
+    expect(1).equal(walletMints.phaseMints[0]);
+    expect(collection.phasesVersion).equal(walletMints.phasesVersion);
+  });
+
+  // Allowlist of the buyer and another wallet, leaves and pairs are hashed like the program does
+  const allowlistLeaves = [buyerKP.publicKey, Keypair.generate().publicKey]
+    .map(wallet => Buffer.from(keccak_256(wallet.toBuffer())));
+  const allowlistRoot = hashMerklePair(allowlistLeaves[0], allowlistLeaves[1]);
+
+  async function allowlistMint(nftKP: Keypair, proof: number[][]) {
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 300000 
+    });
+
+    await program.methods
+      .publicMintNft(
+        'Allowlist NFT',
+        'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG',                     // IPFS Image Hash
+        'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',    // Metadata URI
+        1,                                                                    // Mint phase
+        proof,                                                                // Merkle proof
+      )
+      .accounts({
+        mintNft: {
+          mint: nftKP.publicKey,
+          mintAuthority: buyerKP.publicKey,
+          payer: buyerKP.publicKey,
+          rent: SYSVAR_RENT_PUBKEY,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenAccount: getAssociatedTokenAddressSync(nftKP.publicKey, buyerKP.publicKey),
+          associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          nftPda: getNftPDA(collectionPDA, nftKP.publicKey),
+          masterEdition: getMasterEditionPDA(nftKP.publicKey),
+          metadata: getMetadataPDA(nftKP.publicKey),
+          collectionTokenMint: collectionKP.publicKey,
+          collectionPda: collectionPDA,
+          collectionMetadata: collectionMetadataPDA,
+          collectionMasterEd: collectionMasterEditionPDA,
+          collectionAuthorityRecord: collectionAuthorityRecordPDA,
+        },
+        collectionOwner: provider.wallet.publicKey,
+        walletMintsPda: getWalletMintsPDA(collectionPDA, buyerKP.publicKey),
+        systemProgram: SystemProgram.programId,
+      })
+      .preInstructions([modifyComputeUnits])
+      .signers([buyerKP, nftKP])
+      .rpc();
+  }
+
+  it('Configure allowlist mint phase', async () => {
+    const now = Math.floor(Date.now() / 1000);
+    const collection = await program.account.collectionAccount.fetch(collectionPDA);
+
+    await program.methods
+      .configureMintPhases(new BN(10), [
+        collection.mintPhases[0],
+        {
+          price: new BN(10_000_000),
+          startTime: new BN(now - 60),
+          endTime: new BN(now + 3600),
+          perWalletLimit: 1,
+          merkleRoot: Array.from(allowlistRoot),
+          paymentMint: null,
+        },
+      ])
+      .accounts({
+        owner: provider.wallet.publicKey,
+        collectionPda: collectionPDA,
+        marketplace: marketplacePDA,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const updated = await program.account.collectionAccount.fetch(collectionPDA);
+    expect(Buffer.from(updated.mintPhases[1].merkleRoot).equals(allowlistRoot)).to.be.true;
+  });
+
+  it('Allowlist mint without a valid proof fails', async () => {
+    try {
+      await allowlistMint(Keypair.generate(), []);
+      expect.fail('A missing proof must not pass the allowlist');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('NotOnAllowlist');
+    }
+
+    // A proof node that isn't part of the allowlist tree
+    try {
+      await allowlistMint(Keypair.generate(), [Array.from(keccak_256(Keypair.generate().publicKey.toBuffer()))]);
+      expect.fail('An invalid proof must not pass the allowlist');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('NotOnAllowlist');
+    }
+  });
+
+  it('Allowlist mint NFT', async () => {
+    const allowlistNftKP = Keypair.generate();
+    await allowlistMint(allowlistNftKP, [Array.from(allowlistLeaves[1])]);
+
+    const nft = await program.account.nftAccount.fetch(getNftPDA(collectionPDA, allowlistNftKP.publicKey));
+    expect(allowlistNftKP.publicKey.toString()).equal(nft.tokenMint.toString());
+
+    const walletMints = await program.account.walletMintsAccount.fetch(
+      getWalletMintsPDA(collectionPDA, buyerKP.publicKey)
+    );
+    expect(1).equal(walletMints.phaseMints[1]);
+  });
+
+
+
+  /* ******************************
+            PAYMENT MINTS
+  ****************************** */
+
+  it('List NFT and buy it with an SPL token', async () => {
+    const payer = (provider.wallet as anchor.Wallet).payer;
+    const paymentMint = await createMint(provider.connection, payer, provider.wallet.publicKey, null, 6);
+
+    await program.methods
+      .updateMarketplace(null, null, null, [paymentMint])
+      .accounts({
+        admin: provider.wallet.publicKey,
+        marketplace: marketplacePDA,
+      })
+      .rpc();
+
+    const providerPaymentAccount = await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, provider.wallet.publicKey);
+    const buyerPaymentAccount = await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, buyerKP.publicKey);
+    const treasuryPaymentAccount = await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, treasuryPDA, true);
+    await mintTo(provider.connection, payer, paymentMint, providerPaymentAccount.address, payer, 1_000_000);
+
+    // The buyer of the Dutch auction lists the NFT back for 1 token
+    const price = new BN(1_000_000);
+
+    await program.methods
+      .listNft(price, { escrow: {} })
+      .accounts({
+        seller: buyerKP.publicKey,
+        mint: nftTokenMint,
+        sellerTokenAccount: buyerATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        listingPda: listingPDA,
+        escrowTokenAccount: escrowPDA,
+        marketplace: marketplacePDA,
+        paymentMint: paymentMint,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([buyerKP])
+      .rpc();
+
+    await program.methods
+      .buyNft(price, buyerKP.publicKey)
+      .accounts({
+        buyer: provider.wallet.publicKey,
+        seller: buyerKP.publicKey,
+        mint: nftTokenMint,
+        buyerTokenAccount: nftATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        listingPda: listingPDA,
+        escrowTokenAccount: escrowPDA,
+        metadata: nftMetadataPDA,
+        marketplace: marketplacePDA,
+        treasury: treasuryPDA,
+        paymentMint: paymentMint,
+        buyerPaymentAccount: providerPaymentAccount.address,
+        sellerPaymentAccount: buyerPaymentAccount.address,
+        treasuryPaymentAccount: treasuryPaymentAccount.address,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: providerPaymentAccount.address, isWritable: true, isSigner: false },   // Creators
+      ])
+      .rpc();
+
+    // 2.5% fee and 5% royalties are paid out of the price
+    const treasuryTokens = await getAccount(provider.connection, treasuryPaymentAccount.address);
+    expect(25_000).equal(Number(treasuryTokens.amount));
+
+    const sellerTokens = await getAccount(provider.connection, buyerPaymentAccount.address);
+    expect(925_000).equal(Number(sellerTokens.amount));
+
+    const ata = await getAccount(provider.connection, nftATA);
+    expect(1).equal(Number(ata.amount));
+  });
+
+
+
+  it('Auction NFT for an SPL token', async () => {
+    const payer = (provider.wallet as anchor.Wallet).payer;
+    const paymentMint = await createMint(provider.connection, payer, provider.wallet.publicKey, null, 6);
+
+    await program.methods
+      .updateMarketplace(null, null, null, [paymentMint])
+      .accounts({
+        admin: provider.wallet.publicKey,
+        marketplace: marketplacePDA,
+      })
+      .rpc();
+
+    const providerPaymentAccount = await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, provider.wallet.publicKey);
+    const buyerPaymentAccount = await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, buyerKP.publicKey);
+    const treasuryPaymentAccount = await getOrCreateAssociatedTokenAccount(provider.connection, payer, paymentMint, treasuryPDA, true);
+    await mintTo(provider.connection, payer, paymentMint, buyerPaymentAccount.address, payer, 1_000_000);
+
+    const bidEscrowPDA = getBidEscrowPDA(auctionPDA);
+
+    async function createSplAuction(endTime: BN) {
+      await program.methods
+        .createAuction(new BN(500_000), new BN(100_000), endTime, new BN(0))
+        .accounts({
+          seller: provider.wallet.publicKey,
+          mint: nftTokenMint,
+          sellerTokenAccount: nftATA,
+          collectionPda: collectionPDA,
+          nftPda: nftPDA,
+          auctionPda: auctionPDA,
+          escrowTokenAccount: auctionEscrowPDA,
+          marketplace: marketplacePDA,
+          paymentMint: paymentMint,
+          bidEscrow: bidEscrowPDA,
+          rent: SYSVAR_RENT_PUBKEY,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+        })
+        .rpc();
+    }
+
+    function cancelSplAuction(bidEscrow: PublicKey | null) {
+      return program.methods
+        .cancelAuction()
+        .accounts({
+          seller: provider.wallet.publicKey,
+          mint: nftTokenMint,
+          sellerTokenAccount: nftATA,
+          collectionPda: collectionPDA,
+          nftPda: nftPDA,
+          auctionPda: auctionPDA,
+          escrowTokenAccount: auctionEscrowPDA,
+          bidEscrow: bidEscrow,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+        })
+        .rpc();
+    }
+
+    function settleSplAuction(bidEscrow: PublicKey | null) {
+      return program.methods
+        .settleAuction()
+        .accounts({
+          payer: provider.wallet.publicKey,
+          seller: provider.wallet.publicKey,
+          winner: buyerKP.publicKey,
+          mint: nftTokenMint,
+          winnerTokenAccount: buyerATA,
+          collectionPda: collectionPDA,
+          nftPda: nftPDA,
+          auctionPda: auctionPDA,
+          escrowTokenAccount: auctionEscrowPDA,
+          metadata: nftMetadataPDA,
+          marketplace: marketplacePDA,
+          treasury: treasuryPDA,
+          paymentMint: paymentMint,
+          bidEscrow: bidEscrow,
+          sellerPaymentAccount: providerPaymentAccount.address,
+          treasuryPaymentAccount: treasuryPaymentAccount.address,
+          rent: SYSVAR_RENT_PUBKEY,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: providerPaymentAccount.address, isWritable: true, isSigner: false },   // Creators
+        ])
+        .rpc();
+    }
+
+    // The bid escrow of an SPL token auction can't be left behind
+    await createSplAuction(new BN(Math.floor(Date.now() / 1000) + 3600));
+    try {
+      await cancelSplAuction(null);
+      expect.fail('The bid escrow must be closed with the auction');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('MissingBidEscrow');
+    }
+    await cancelSplAuction(bidEscrowPDA);
+
+    await createSplAuction(new BN(Math.floor(Date.now() / 1000) + 10));
+
+    await program.methods
+      .placeBid(new BN(500_000))
+      .accounts({
+        bidder: buyerKP.publicKey,
+        previousBidder: buyerKP.publicKey,
+        auctionPda: auctionPDA,
+        nftPda: nftPDA,
+        marketplace: marketplacePDA,
+        paymentMint: paymentMint,
+        bidEscrow: bidEscrowPDA,
+        bidderPaymentAccount: buyerPaymentAccount.address,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([buyerKP])
+      .rpc();
+
+    const escrowTokens = await getAccount(provider.connection, bidEscrowPDA);
+    expect(500_000).equal(Number(escrowTokens.amount));
+
+    // Wait for the auction to end
+    await new Promise(resolve => setTimeout(resolve, 12000));
+
+    try {
+      await settleSplAuction(null);
+      expect.fail('The bid escrow must be closed with the auction');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('MissingBidEscrow');
+    }
+    await settleSplAuction(bidEscrowPDA);
+
+    // 2.5% fee, the seller is also the creator and gets the rest
+    const treasuryTokens = await getAccount(provider.connection, treasuryPaymentAccount.address);
+    expect(12_500).equal(Number(treasuryTokens.amount));
+
+    const sellerTokens = await getAccount(provider.connection, providerPaymentAccount.address);
+    expect(487_500).equal(Number(sellerTokens.amount));
+
+    const ata = await getAccount(provider.connection, buyerATA);
+    expect(1).equal(Number(ata.amount));
+
+    // Hand the NFT back to the master holder of the edition tests
+    await transfer(provider.connection, payer, buyerATA, nftATA, buyerKP, 1);
+  });
+
+
+
+  /* ******************************
+          METADATA UPDATES
+  ****************************** */
+
+  const updatedImageHash = 'bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi';
+
+  it('Update collection', async () => {
+    await program.methods
+      .updateCollection(
+        'Updated Collection',
+        updatedImageHash,
+        'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',
+      )
+      .accounts({
+        owner: provider.wallet.publicKey,
+        collectionPda: collectionPDA,
+        metadata: collectionMetadataPDA,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const collection = await program.account.collectionAccount.fetch(collectionPDA);
+    expect('Updated Collection').equal(collection.name);
+    expect(updatedImageHash).equal(collection.ipfsImageHash);
+
+    const metaplex = Metaplex.make(provider.connection);
+    const nft = await metaplex.nfts().findByMint({ mintAddress: collectionTokenMint });
+    expect('Updated Collection').equal(nft.name);
+  });
+
+  it('Update NFT', async () => {
+    await program.methods
+      .updateNft(
+        'Updated NFT',
+        updatedImageHash,
+        'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',
+      )
+      .accounts({
+        owner: provider.wallet.publicKey,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        metadata: nftMetadataPDA,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const nftAccount = await program.account.nftAccount.fetch(nftPDA);
+    expect('Updated NFT').equal(nftAccount.name);
+    expect(updatedImageHash).equal(nftAccount.ipfsImageHash);
+  });
+
+
+
+  /* ******************************
+         COLLECTION OWNERSHIP
+  ****************************** */
+
+  async function transferCollectionOwner(owner: Keypair | null, newOwner: Keypair | null) {
+    const ownerKey = owner ? owner.publicKey : provider.wallet.publicKey;
+    const newOwnerKey = newOwner ? newOwner.publicKey : provider.wallet.publicKey;
+
+    await program.methods
+      .proposeCollectionOwner(newOwnerKey)
+      .accounts({
+        owner: ownerKey,
+        collectionPda: collectionPDA,
+      })
+      .signers(owner ? [owner] : [])
+      .rpc();
+
+    await program.methods
+      .acceptCollectionOwner()
+      .accounts({
+        newOwner: newOwnerKey,
+        collectionPda: collectionPDA,
+      })
+      .signers(newOwner ? [newOwner] : [])
+      .rpc();
+  }
+
+  it('Transfer collection ownership', async () => {
+    await transferCollectionOwner(null, buyerKP);
+
+    let collection = await program.account.collectionAccount.fetch(collectionPDA);
+    expect(buyerKP.publicKey.toString()).equal(collection.owner.toString());
+    expect(collection.pendingOwner).to.be.null;
+
+    // Hand it back so the following tests keep using the provider wallet
+    await transferCollectionOwner(buyerKP, null);
+
+    collection = await program.account.collectionAccount.fetch(collectionPDA);
+    expect(provider.wallet.publicKey.toString()).equal(collection.owner.toString());
+  });
+
+
+
+  /* ******************************
+           LOCKED COLLECTIONS
+  ****************************** */
+
+  it('Lock collection', async () => {
+    await program.methods
+      .lockCollection()
+      .accounts({
+        owner: provider.wallet.publicKey,
+        collectionPda: collectionPDA,
+        metadata: collectionMetadataPDA,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      })
+      .rpc();
+
+    const collection = await program.account.collectionAccount.fetch(collectionPDA);
+    expect(true).equal(collection.locked);
+
+    const metaplex = Metaplex.make(provider.connection);
+    const nft = await metaplex.nfts().findByMint({ mintAddress: collectionTokenMint });
+    expect(false).equal(nft.isMutable);
+
+    try {
+      await program.methods
+        .updateCollection('Locked Collection', updatedImageHash, 'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA')
+        .accounts({
+          owner: provider.wallet.publicKey,
+          collectionPda: collectionPDA,
+          metadata: collectionMetadataPDA,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      expect.fail('Locked collections can not be updated');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('CollectionLocked');
+    }
+
+    try {
+      await program.methods
+        .releaseUpdateAuthority(Keypair.generate().publicKey)
+        .accounts({
+          owner: provider.wallet.publicKey,
+          collectionPda: collectionPDA,
+          metadata: collectionMetadataPDA,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts([
+          { pubkey: nftMetadataPDA, isWritable: true, isSigner: false },
+        ])
+        .rpc();
+      expect.fail('Locked collections can not release their update authority');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('CollectionLocked');
+    }
+  });
+
+
+
+  /* ******************************
+              BURN NFTs
+  ****************************** */
+
+  it('Burn NFT', async () => {
+    const publicNftPDA = getNftPDA(collectionPDA, publicNftTokenMint);
+    const before = await program.account.collectionAccount.fetch(collectionPDA);
+
+    await program.methods
+      .burnNft()
+      .accounts({
+        holder: buyerKP.publicKey,
+        mint: publicNftTokenMint,
+        holderTokenAccount: getAssociatedTokenAddressSync(publicNftTokenMint, buyerKP.publicKey),
+        collectionPda: collectionPDA,
+        nftPda: publicNftPDA,
+        metadata: getMetadataPDA(publicNftTokenMint),
+        masterEdition: getMasterEditionPDA(publicNftTokenMint),
+        collectionMetadata: collectionMetadataPDA,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([buyerKP])
+      .rpc();
+
+    const nft = await program.account.nftAccount.fetchNullable(publicNftPDA);
+    expect(nft).to.be.null;
+
+    const after = await program.account.collectionAccount.fetch(collectionPDA);
+    expect(before.mintedCount.toNumber()).equal(after.mintedCount.toNumber());
+    expect(before.burnedCount.toNumber() + 1).equal(after.burnedCount.toNumber());
+  });
+
+
+
+  /* ******************************
+              EDITIONS
+  ****************************** */
+
+  it('Set edition price', async () => {
+    await program.methods
+      .setEditionPrice(new BN(20_000_000))
+      .accounts({
+        owner: provider.wallet.publicKey,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+      })
+      .rpc();
+
+    const nft = await program.account.nftAccount.fetch(nftPDA);
+    expect(20_000_000).equal(nft.editionPrice.toNumber());
+  });
+
+  it('Print edition', async () => {
+    const editionKP = Keypair.generate();
+    const editionTokenMint = editionKP.publicKey;
+    const editionPDA = getNftPDA(collectionPDA, editionTokenMint);
+
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 400000 
+    });
+
+    await program.methods
+      .printEdition()
+      .accounts({
+        buyer: buyerKP.publicKey,
+        masterHolder: provider.wallet.publicKey,
+        masterMint: nftTokenMint,
+        masterTokenAccount: nftATA,
+        collectionPda: collectionPDA,
+        nftPda: nftPDA,
+        masterMetadata: nftMetadataPDA,
+        masterEdition: nftMasterEditionPDA,
+        editionMarker: getEditionMarkerPDA(nftTokenMint, 1),
+        editionMint: editionTokenMint,
+        editionTokenAccount: getAssociatedTokenAddressSync(editionTokenMint, buyerKP.publicKey),
+        editionMetadata: getMetadataPDA(editionTokenMint),
+        editionEdition: getMasterEditionPDA(editionTokenMint),
+        editionPda: editionPDA,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: provider.wallet.publicKey, isWritable: true, isSigner: false },   // Creators
+      ])
+      .preInstructions([modifyComputeUnits])
+      .signers([buyerKP, editionKP])
+      .rpc();
+
+    const edition = await program.account.nftAccount.fetch(editionPDA);
+    expect(1).equal(edition.edition.toNumber());
+    expect(nftTokenMint.toString()).not.equal(edition.tokenMint.toString());
+  });
+
+
+
+  /* ******************************
+         PROGRAMMABLE NFTS
+  ****************************** */
+
+  const pCollectionKP = Keypair.generate();
+  const pCollectionTokenMint = pCollectionKP.publicKey;
+  const pCollectionPDA = getCollectionPDA(pCollectionTokenMint);
+  const pCollectionMetadataPDA = getMetadataPDA(pCollectionTokenMint);
+  const pCollectionMasterEditionPDA = getMasterEditionPDA(pCollectionTokenMint);
+  const pCollectionAuthorityRecordPDA = getCollectionAuthorityRecordPDA(pCollectionTokenMint, pCollectionPDA);
+
+  const pNftKP = Keypair.generate();
+  const pNftTokenMint = pNftKP.publicKey;
+  const pNftATA = getAssociatedTokenAddressSync(pNftTokenMint, provider.wallet.publicKey);
+  const pNftPDA = getNftPDA(pCollectionPDA, pNftTokenMint);
+  const pNftMetadataPDA = getMetadataPDA(pNftTokenMint);
+  const pNftMasterEditionPDA = getMasterEditionPDA(pNftTokenMint);
+  const pBuyerATA = getAssociatedTokenAddressSync(pNftTokenMint, buyerKP.publicKey);
+
+  // Accounts of a Metaplex Transfer of the pNFT between two token accounts
+  function pNftTransferAccounts(tokenAccount: PublicKey, destinationTokenAccount: PublicKey) {
+    return {
+      edition: pNftMasterEditionPDA,
+      ownerTokenRecord: getTokenRecordPDA(pNftTokenMint, tokenAccount),
+      destinationTokenRecord: getTokenRecordPDA(pNftTokenMint, destinationTokenAccount),
+      authorizationRulesProgram: null,
+      authorizationRules: null,
+      sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+      tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+    };
+  }
+
+  it('Mint programmable NFT', async () => {
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 400000 
+    });
+
+    await program.methods
+      .mintCollection(
+        'My Programmable Collection',
+        'MPC',
+        'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG',                     // IPFS Image Hash
+        'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',    // Metadata URI
+        500,                                                                  // Royalties (5%)
+        [{ address: provider.wallet.publicKey, share: 100 }],                 // Creators
+        { programmable: true, ruleSet: null },                                // Programmable config
+      )
+      .accounts({
+        mint: pCollectionTokenMint,
+        mintAuthority: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenAccount: getAssociatedTokenAddressSync(pCollectionTokenMint, provider.wallet.publicKey),
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        masterEdition: pCollectionMasterEditionPDA,
+        metadata: pCollectionMetadataPDA,
+        collectionAuthorityRecord: pCollectionAuthorityRecordPDA,
+        collectionPda: pCollectionPDA,
+      })
+      .preInstructions([modifyComputeUnits])
+      .signers([pCollectionKP])
+      .rpc();
+
+    await program.methods
+      .mintNftFromCollection(
+        'First pNFT',
+        'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG',                     // IPFS Image Hash
+        'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',    // Metadata URI
+        new BN(0),                                                            // Edition max supply
+        new BN(0),                                                            // Edition price
+      )
+      .accounts({
+        mint: pNftTokenMint,
+        mintAuthority: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenAccount: pNftATA,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        nftPda: pNftPDA,
+        masterEdition: pNftMasterEditionPDA,
+        metadata: pNftMetadataPDA,
+        collectionTokenMint: pCollectionTokenMint,
+        collectionPda: pCollectionPDA,
+        collectionMetadata: pCollectionMetadataPDA,
+        collectionMasterEd: pCollectionMasterEditionPDA,
+        collectionAuthorityRecord: pCollectionAuthorityRecordPDA,
+        tokenRecord: getTokenRecordPDA(pNftTokenMint, pNftATA),
+        sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        authorizationRulesProgram: null,
+        authorizationRules: null,
+      })
+      .preInstructions([modifyComputeUnits])
+      .signers([pNftKP])
+      .rpc();
+
+    const ata = await getAccount(provider.connection, pNftATA);
+    expect(1).equal(Number(ata.amount));
+    expect(true).equal(ata.isFrozen);
+
+    const metaplex = Metaplex.make(provider.connection);
+    const nft = await metaplex.nfts().findByMint({ mintAddress: pNftTokenMint });
+    expect(pCollectionTokenMint.toString()).equal(nft.collection.address.toString());
+    expect(true).equal(nft.collection.verified);
+    expect(true).equal(nft.creators[0].verified);
+  });
+
+  it('List programmable NFT and buy it', async () => {
+    const price = new BN(500_000_000);
+    const pListingPDA = getListingPDA(pNftPDA);
+
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 400000 
+    });
+
+    await program.methods
+      .listNft(price, { saleDelegate: {} })
+      .accounts({
+        seller: provider.wallet.publicKey,
+        mint: pNftTokenMint,
+        sellerTokenAccount: pNftATA,
+        collectionPda: pCollectionPDA,
+        nftPda: pNftPDA,
+        listingPda: pListingPDA,
+        escrowTokenAccount: null,
+        marketplace: marketplacePDA,
+        metadata: pNftMetadataPDA,
+        edition: pNftMasterEditionPDA,
+        ownerTokenRecord: getTokenRecordPDA(pNftTokenMint, pNftATA),
+        authorizationRulesProgram: null,
+        authorizationRules: null,
+        sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([modifyComputeUnits])
+      .rpc();
+
+    // The pNFT stays in the seller wallet while listed
+    const sellerATA = await getAccount(provider.connection, pNftATA);
+    expect(1).equal(Number(sellerATA.amount));
+    expect(pListingPDA.toString()).equal(sellerATA.delegate.toString());
+
+    const airdrop = await provider.connection.requestAirdrop(buyerKP.publicKey, 1_000_000_000);
+    await provider.connection.confirmTransaction(airdrop);
+
+    await program.methods
+      .buyNft(price, provider.wallet.publicKey)
+      .accounts({
+        buyer: buyerKP.publicKey,
+        seller: provider.wallet.publicKey,
+        mint: pNftTokenMint,
+        buyerTokenAccount: pBuyerATA,
+        collectionPda: pCollectionPDA,
+        nftPda: pNftPDA,
+        listingPda: pListingPDA,
+        escrowTokenAccount: null,
+        sellerTokenAccount: pNftATA,
+        metadata: pNftMetadataPDA,
+        marketplace: marketplacePDA,
+        treasury: treasuryPDA,
+        edition: pNftMasterEditionPDA,
+        ownerTokenRecord: getTokenRecordPDA(pNftTokenMint, pNftATA),
+        destinationTokenRecord: getTokenRecordPDA(pNftTokenMint, pBuyerATA),
+        authorizationRulesProgram: null,
+        authorizationRules: null,
+        sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: provider.wallet.publicKey, isWritable: true, isSigner: false },   // Creators
+      ])
+      .preInstructions([modifyComputeUnits])
+      .signers([buyerKP])
+      .rpc();
+
+    const ata = await getAccount(provider.connection, pBuyerATA);
+    expect(1).equal(Number(ata.amount));
+  });
+
+  const pOfferPDA = getOfferPDA(pNftPDA, provider.wallet.publicKey);
+
+  it('Accept offer on a programmable NFT', async () => {
+    const price = new BN(200_000_000);
+
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 400000 
+    });
+
+    await program.methods
+      .makeOffer(price, new BN(Math.floor(Date.now() / 1000) + 3600))
+      .accounts({
+        bidder: provider.wallet.publicKey,
+        mint: pNftTokenMint,
+        collectionPda: pCollectionPDA,
+        nftPda: pNftPDA,
+        offerPda: pOfferPDA,
+        marketplace: marketplacePDA,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .acceptOffer(price)
+      .accounts({
+        seller: buyerKP.publicKey,
+        bidder: provider.wallet.publicKey,
+        mint: pNftTokenMint,
+        sellerTokenAccount: pBuyerATA,
+        bidderTokenAccount: pNftATA,
+        collectionPda: pCollectionPDA,
+        nftPda: pNftPDA,
+        offerPda: pOfferPDA,
+        metadata: pNftMetadataPDA,
+        marketplace: marketplacePDA,
+        treasury: treasuryPDA,
+        ...pNftTransferAccounts(pBuyerATA, pNftATA),
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: provider.wallet.publicKey, isWritable: true, isSigner: false },   // Creators
+      ])
+      .preInstructions([modifyComputeUnits])
+      .signers([buyerKP])
+      .rpc();
+
+    const offer = await program.account.offerAccount.fetchNullable(pOfferPDA);
+    expect(offer).to.be.null;
+
+    // Metaplex freezes the pNFT again in the bidder wallet
+    const ata = await getAccount(provider.connection, pNftATA);
+    expect(1).equal(Number(ata.amount));
+    expect(true).equal(ata.isFrozen);
+  });
+
+  const pCollectionBidPDA = getCollectionBidPDA(pCollectionPDA, buyerKP.publicKey);
+
+  it('Fill collection bid with a programmable NFT', async () => {
+    const price = new BN(200_000_000);
+
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 400000 
+    });
+
+    await program.methods
+      .makeCollectionBid(price, 1)
+      .accounts({
+        bidder: buyerKP.publicKey,
+        collectionPda: pCollectionPDA,
+        collectionBidPda: pCollectionBidPDA,
+        marketplace: marketplacePDA,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyerKP])
+      .rpc();
+
+    await program.methods
+      .fillCollectionBid(price)
+      .accounts({
+        seller: provider.wallet.publicKey,
+        bidder: buyerKP.publicKey,
+        mint: pNftTokenMint,
+        sellerTokenAccount: pNftATA,
+        bidderTokenAccount: pBuyerATA,
+        collectionPda: pCollectionPDA,
+        nftPda: pNftPDA,
+        collectionBidPda: pCollectionBidPDA,
+        metadata: pNftMetadataPDA,
+        marketplace: marketplacePDA,
+        treasury: treasuryPDA,
+        ...pNftTransferAccounts(pNftATA, pBuyerATA),
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: provider.wallet.publicKey, isWritable: true, isSigner: false },   // Creators
+      ])
+      .preInstructions([modifyComputeUnits])
+      .rpc();
+
+    const bid = await program.account.collectionBidAccount.fetchNullable(pCollectionBidPDA);
+    expect(bid).to.be.null;
+
+    const ata = await getAccount(provider.connection, pBuyerATA);
+    expect(1).equal(Number(ata.amount));
+    expect(true).equal(ata.isFrozen);
+  });
+
+  const pAuctionPDA = getAuctionPDA(pNftPDA);
+  const pAuctionEscrowPDA = getAuctionEscrowPDA(pNftPDA);
+
+  async function createProgrammableAuction(endTime: BN) {
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 400000 
+    });
+
+    await program.methods
+      .createAuction(new BN(100_000_000), new BN(10_000_000), endTime, new BN(0))
+      .accounts({
+        seller: buyerKP.publicKey,
+        mint: pNftTokenMint,
+        sellerTokenAccount: pBuyerATA,
+        collectionPda: pCollectionPDA,
+        nftPda: pNftPDA,
+        auctionPda: pAuctionPDA,
+        escrowTokenAccount: pAuctionEscrowPDA,
+        marketplace: marketplacePDA,
+        metadata: pNftMetadataPDA,
+        ...pNftTransferAccounts(pBuyerATA, pAuctionEscrowPDA),
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .preInstructions([modifyComputeUnits])
+      .signers([buyerKP])
+      .rpc();
+
+    // The pNFT waits frozen in the auction escrow
+    const escrow = await getAccount(provider.connection, pAuctionEscrowPDA);
+    expect(1).equal(Number(escrow.amount));
+    expect(true).equal(escrow.isFrozen);
+  }
+
+  it('Cancel auction of a programmable NFT', async () => {
+    await createProgrammableAuction(new BN(Math.floor(Date.now() / 1000) + 3600));
+
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 400000 
+    });
+
+    await program.methods
+      .cancelAuction()
+      .accounts({
+        seller: buyerKP.publicKey,
+        mint: pNftTokenMint,
+        sellerTokenAccount: pBuyerATA,
+        collectionPda: pCollectionPDA,
+        nftPda: pNftPDA,
+        auctionPda: pAuctionPDA,
+        escrowTokenAccount: pAuctionEscrowPDA,
+        metadata: pNftMetadataPDA,
+        ...pNftTransferAccounts(pAuctionEscrowPDA, pBuyerATA),
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .preInstructions([modifyComputeUnits])
+      .signers([buyerKP])
+      .rpc();
+
+    const auction = await program.account.auctionAccount.fetchNullable(pAuctionPDA);
+    expect(auction).to.be.null;
+
+    const ata = await getAccount(provider.connection, pBuyerATA);
+    expect(1).equal(Number(ata.amount));
+    expect(true).equal(ata.isFrozen);
+  });
+
+  it('Settle auction of a programmable NFT', async () => {
+    await createProgrammableAuction(new BN(Math.floor(Date.now() / 1000) + 5));
+
+    await program.methods
+      .placeBid(new BN(100_000_000))
+      .accounts({
+        bidder: provider.wallet.publicKey,
+        previousBidder: provider.wallet.publicKey,
+        auctionPda: pAuctionPDA,
+        nftPda: pNftPDA,
+        marketplace: marketplacePDA,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    await new Promise(resolve => setTimeout(resolve, 7000));
+
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 400000 
+    });
+
+    await program.methods
+      .settleAuction()
+      .accounts({
+        payer: provider.wallet.publicKey,
+        seller: buyerKP.publicKey,
+        winner: provider.wallet.publicKey,
+        mint: pNftTokenMint,
+        winnerTokenAccount: pNftATA,
+        collectionPda: pCollectionPDA,
+        nftPda: pNftPDA,
+        auctionPda: pAuctionPDA,
+        escrowTokenAccount: pAuctionEscrowPDA,
+        metadata: pNftMetadataPDA,
+        marketplace: marketplacePDA,
+        treasury: treasuryPDA,
+        ...pNftTransferAccounts(pAuctionEscrowPDA, pNftATA),
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .remainingAccounts([
+        { pubkey: provider.wallet.publicKey, isWritable: true, isSigner: false },   // Creators
+      ])
+      .preInstructions([modifyComputeUnits])
+      .rpc();
+
+    const auction = await program.account.auctionAccount.fetchNullable(pAuctionPDA);
+    expect(auction).to.be.null;
+
+    // Metaplex leaves the escrow thawed so it can be closed
+    const escrow = await provider.connection.getAccountInfo(pAuctionEscrowPDA);
+    expect(escrow).to.be.null;
+
+    const ata = await getAccount(provider.connection, pNftATA);
+    expect(1).equal(Number(ata.amount));
+    expect(true).equal(ata.isFrozen);
+  });
+
+  it('Update programmable NFT', async () => {
+    await program.methods
+      .updateNft(
+        'Updated pNFT',
+        'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG',
+        'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',
+      )
+      .accounts({
+        owner: provider.wallet.publicKey,
+        collectionPda: pCollectionPDA,
+        nftPda: pNftPDA,
+        metadata: pNftMetadataPDA,
+        mint: pNftTokenMint,
+        authorizationRulesProgram: null,
+        authorizationRules: null,
+        sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const nftAccount = await program.account.nftAccount.fetch(pNftPDA);
+    expect('Updated pNFT').equal(nftAccount.name);
+
+    const metaplex = Metaplex.make(provider.connection);
+    const nft = await metaplex.nfts().findByMint({ mintAddress: pNftTokenMint });
+    expect('Updated pNFT').equal(nft.name);
+  });
+
+  const pMasterKP = Keypair.generate();
+  const pMasterTokenMint = pMasterKP.publicKey;
+  const pMasterATA = getAssociatedTokenAddressSync(pMasterTokenMint, provider.wallet.publicKey);
+  const pMasterPDA = getNftPDA(pCollectionPDA, pMasterTokenMint);
+  const pMasterMetadataPDA = getMetadataPDA(pMasterTokenMint);
+  const pMasterEditionPDA = getMasterEditionPDA(pMasterTokenMint);
+
+  it('Print edition of a programmable NFT', async () => {
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 400000 
+    });
+
+    await program.methods
+      .mintNftFromCollection(
+        'Printable pNFT',
+        'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG',                     // IPFS Image Hash
+        'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',    // Metadata URI
+        new BN(1),                                                            // Edition max supply
+        new BN(0),                                                            // Edition price
+      )
+      .accounts({
+        mint: pMasterTokenMint,
+        mintAuthority: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenAccount: pMasterATA,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        nftPda: pMasterPDA,
+        masterEdition: pMasterEditionPDA,
+        metadata: pMasterMetadataPDA,
+        collectionTokenMint: pCollectionTokenMint,
+        collectionPda: pCollectionPDA,
+        collectionMetadata: pCollectionMetadataPDA,
+        collectionMasterEd: pCollectionMasterEditionPDA,
+        collectionAuthorityRecord: pCollectionAuthorityRecordPDA,
+        tokenRecord: getTokenRecordPDA(pMasterTokenMint, pMasterATA),
+        sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        authorizationRulesProgram: null,
+        authorizationRules: null,
+      })
+      .preInstructions([modifyComputeUnits])
+      .signers([pMasterKP])
+      .rpc();
+
+    const editionKP = Keypair.generate();
+    const editionTokenMint = editionKP.publicKey;
+    const editionATA = getAssociatedTokenAddressSync(editionTokenMint, buyerKP.publicKey);
+    const editionPDA = getNftPDA(pCollectionPDA, editionTokenMint);
+
+    await program.methods
+      .printEdition()
+      .accounts({
+        buyer: buyerKP.publicKey,
+        masterHolder: provider.wallet.publicKey,
+        masterMint: pMasterTokenMint,
+        masterTokenAccount: pMasterATA,
+        collectionPda: pCollectionPDA,
+        nftPda: pMasterPDA,
+        masterMetadata: pMasterMetadataPDA,
+        masterEdition: pMasterEditionPDA,
+        editionMarker: getEditionMarkerPDA(pMasterTokenMint, 1),
+        editionMint: editionTokenMint,
+        editionTokenAccount: editionATA,
+        editionMetadata: getMetadataPDA(editionTokenMint),
+        editionEdition: getMasterEditionPDA(editionTokenMint),
+        editionPda: editionPDA,
+        editionTokenRecord: getTokenRecordPDA(editionTokenMint, editionATA),
+        sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+      })
+      .preInstructions([modifyComputeUnits])
+      .signers([buyerKP, editionKP])
+      .rpc();
+
+    const edition = await program.account.nftAccount.fetch(editionPDA);
+    expect(1).equal(edition.edition.toNumber());
+
+    // Printed pNFT editions are frozen in the buyer wallet like any pNFT
+    const ata = await getAccount(provider.connection, editionATA);
+    expect(1).equal(Number(ata.amount));
+    expect(true).equal(ata.isFrozen);
+  });
+
+  it('Burn programmable NFT', async () => {
+    const before = await program.account.collectionAccount.fetch(pCollectionPDA);
+
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 400000 
+    });
+
+    await program.methods
+      .burnNft()
+      .accounts({
+        holder: provider.wallet.publicKey,
+        mint: pNftTokenMint,
+        holderTokenAccount: pNftATA,
+        collectionPda: pCollectionPDA,
+        nftPda: pNftPDA,
+        metadata: pNftMetadataPDA,
+        masterEdition: pNftMasterEditionPDA,
+        collectionMetadata: pCollectionMetadataPDA,
+        tokenRecord: getTokenRecordPDA(pNftTokenMint, pNftATA),
+        sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([modifyComputeUnits])
+      .rpc();
+
+    const nft = await program.account.nftAccount.fetchNullable(pNftPDA);
+    expect(nft).to.be.null;
+
+    const metadata = await provider.connection.getAccountInfo(pNftMetadataPDA);
+    expect(metadata).to.be.null;
+
+    const after = await program.account.collectionAccount.fetch(pCollectionPDA);
+    expect(before.burnedCount.toNumber() + 1).equal(after.burnedCount.toNumber());
+  });
+
+  it('Release update authority of programmable NFTs', async () => {
+    const newAuthorityKP = Keypair.generate();
+
+    function releaseProgrammable(remainingAccounts: { pubkey: PublicKey, isWritable: boolean, isSigner: boolean }[]) {
+      return program.methods
+        .releaseUpdateAuthority(newAuthorityKP.publicKey)
+        .accounts({
+          owner: provider.wallet.publicKey,
+          collectionPda: pCollectionPDA,
+          metadata: pCollectionMetadataPDA,
+          authorizationRulesProgram: null,
+          authorizationRules: null,
+          sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(remainingAccounts)
+        .rpc();
+    }
+
+    // pNFTs are released with their metadata followed by their mint
+    try {
+      await releaseProgrammable([
+        { pubkey: pMasterMetadataPDA, isWritable: true, isSigner: false },
+      ]);
+      expect.fail('pNFTs need their mint to be released');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('MissingProgrammableAccount');
+    }
+
+    await releaseProgrammable([
+      { pubkey: pMasterMetadataPDA, isWritable: true, isSigner: false },
+      { pubkey: pMasterTokenMint, isWritable: false, isSigner: false },
+    ]);
+
+    const metaplex = Metaplex.make(provider.connection);
+    const collection = await metaplex.nfts().findByMint({ mintAddress: pCollectionTokenMint });
+    expect(newAuthorityKP.publicKey.toString()).equal(collection.updateAuthorityAddress.toString());
+    const nft = await metaplex.nfts().findByMint({ mintAddress: pMasterTokenMint });
+    expect(newAuthorityKP.publicKey.toString()).equal(nft.updateAuthorityAddress.toString());
+  });
+
+
+
+  /* ******************************
+              CREATORS
+  ****************************** */
+
+  it('Co-creator signs collection', async () => {
+    const coCreatorKP = Keypair.generate();
+    const sharedCollectionKP = Keypair.generate();
+    const sharedCollectionTokenMint = sharedCollectionKP.publicKey;
+    const sharedCollectionPDA = getCollectionPDA(sharedCollectionTokenMint);
+    const sharedCollectionMetadataPDA = getMetadataPDA(sharedCollectionTokenMint);
+
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 300000 
+    });
+
+    await program.methods
+      .mintCollection(
+        'Shared Collection',
+        'SHC',
+        'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG',                     // IPFS Image Hash
+        'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',    // Metadata URI
+        500,                                                                  // Royalties (5%)
+        [                                                                     // Creators
+          { address: provider.wallet.publicKey, share: 60 },
+          { address: coCreatorKP.publicKey, share: 40 },
+        ],
+        { programmable: false, ruleSet: null },                               // Programmable config
+      )
+      .accounts({
+        mint: sharedCollectionTokenMint,
+        mintAuthority: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenAccount: getAssociatedTokenAddressSync(sharedCollectionTokenMint, provider.wallet.publicKey),
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        masterEdition: getMasterEditionPDA(sharedCollectionTokenMint),
+        metadata: sharedCollectionMetadataPDA,
+        collectionAuthorityRecord: getCollectionAuthorityRecordPDA(sharedCollectionTokenMint, sharedCollectionPDA),
+        collectionPda: sharedCollectionPDA,
+      })
+      .preInstructions([modifyComputeUnits])
+      .signers([sharedCollectionKP])
+      .rpc();
+
+    await program.methods
+      .signCreator()
+      .accounts({
+        creator: coCreatorKP.publicKey,
+        collectionPda: sharedCollectionPDA,
+        metadata: sharedCollectionMetadataPDA,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([coCreatorKP])
+      .rpc();
+
+    const metaplex = Metaplex.make(provider.connection);
+    const nft = await metaplex.nfts().findByMint({ mintAddress: sharedCollectionTokenMint });
+    expect(2).equal(nft.creators.length);
+    expect(nft.creators.every(c => c.verified)).to.be.true;
+  });
+
+
+
+  /* ******************************
+              MINTERS
+  ****************************** */
+
+  it('Grant and revoke minting rights', async () => {
+    const minterKP = Keypair.generate();
+    const minterPDA = getMinterPDA(collectionPDA, minterKP.publicKey);
+
+    const airdrop = await provider.connection.requestAirdrop(minterKP.publicKey, 1_000_000_000);
+    await provider.connection.confirmTransaction(airdrop);
+
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 300000 
+    });
+
+    const mintAsMinter = (mintKP: Keypair, withRights: boolean) => program.methods
+      .mintNftFromCollection(
+        'Minter NFT',
+        'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG',                     // IPFS Image Hash
+        'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',    // Metadata URI
+        new BN(0),                                                            // Edition max supply
+        new BN(0),                                                            // Edition price
+      )
+      .accounts({
+        mint: mintKP.publicKey,
+        mintAuthority: minterKP.publicKey,
+        payer: minterKP.publicKey,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenAccount: getAssociatedTokenAddressSync(mintKP.publicKey, minterKP.publicKey),
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        nftPda: getNftPDA(collectionPDA, mintKP.publicKey),
+        masterEdition: getMasterEditionPDA(mintKP.publicKey),
+        metadata: getMetadataPDA(mintKP.publicKey),
+        collectionTokenMint: collectionTokenMint,
+        collectionPda: collectionPDA,
+        minterPda: withRights ? minterPDA : null,
+        collectionMetadata: collectionMetadataPDA,
+        collectionMasterEd: collectionMasterEditionPDA,
+        collectionAuthorityRecord: collectionAuthorityRecordPDA,
+      })
+      .preInstructions([modifyComputeUnits])
+      .signers([minterKP, mintKP])
+      .rpc();
+
+    try {
+      await mintAsMinter(Keypair.generate(), false);
+      expect.fail('Wallets without minting rights can not mint');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('NotAllowedToMint');
+    }
+
+    await program.methods
+      .grantMintingRights()
+      .accounts({
+        owner: provider.wallet.publicKey,
+        collectionPda: collectionPDA,
+        minter: minterKP.publicKey,
+        minterPda: minterPDA,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const minterAccount = await program.account.minterAccount.fetch(minterPDA);
+    expect(provider.wallet.publicKey.toString()).equal(minterAccount.grantedBy.toString());
+
+    const mintKP = Keypair.generate();
+    await mintAsMinter(mintKP, true);
+
+    const ata = await getAccount(
+      provider.connection,
+      getAssociatedTokenAddressSync(mintKP.publicKey, minterKP.publicKey),
+    );
+    expect(1).equal(Number(ata.amount));
+
+    // A new owner doesn't inherit the minters granted by the previous one
+    await transferCollectionOwner(null, buyerKP);
+    try {
+      await mintAsMinter(Keypair.generate(), true);
+      expect.fail('Minting rights granted by a previous owner are not valid');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('NotAllowedToMint');
+    }
+    await transferCollectionOwner(buyerKP, null);
+
+    await program.methods
+      .revokeMintingRights()
+      .accounts({
+        owner: provider.wallet.publicKey,
+        collectionPda: collectionPDA,
+        minter: minterKP.publicKey,
+        minterPda: minterPDA,
+      })
+      .rpc();
+
+    const minter = await program.account.minterAccount.fetchNullable(minterPDA);
+    expect(minter).to.be.null;
+  });
+
+
+
+  /* ******************************
+          UPDATE AUTHORITY
+  ****************************** */
+
+  it('Release update authority', async () => {
+    const newAuthorityKP = Keypair.generate();
+    const releasedCollectionKP = Keypair.generate();
+    const releasedCollectionTokenMint = releasedCollectionKP.publicKey;
+    const releasedCollectionPDA = getCollectionPDA(releasedCollectionTokenMint);
+    const releasedCollectionMetadataPDA = getMetadataPDA(releasedCollectionTokenMint);
+
+    const modifyComputeUnits = ComputeBudgetProgram.setComputeUnitLimit({ 
+      units: 300000 
+    });
+
+    await program.methods
+      .mintCollection(
+        'Released Collection',
+        'REL',
+        'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG',                     // IPFS Image Hash
+        'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',    // Metadata URI
+        500,                                                                  // Royalties (5%)
+        [{ address: provider.wallet.publicKey, share: 100 }],                 // Creators
+        { programmable: false, ruleSet: null },                               // Programmable config
+      )
+      .accounts({
+        mint: releasedCollectionTokenMint,
+        mintAuthority: provider.wallet.publicKey,
+        payer: provider.wallet.publicKey,
+        rent: SYSVAR_RENT_PUBKEY,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenAccount: getAssociatedTokenAddressSync(releasedCollectionTokenMint, provider.wallet.publicKey),
+        associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        masterEdition: getMasterEditionPDA(releasedCollectionTokenMint),
+        metadata: releasedCollectionMetadataPDA,
+        collectionAuthorityRecord: getCollectionAuthorityRecordPDA(releasedCollectionTokenMint, releasedCollectionPDA),
+        collectionPda: releasedCollectionPDA,
+      })
+      .preInstructions([modifyComputeUnits])
+      .signers([releasedCollectionKP])
+      .rpc();
+
+    await program.methods
+      .releaseUpdateAuthority(newAuthorityKP.publicKey)
+      .accounts({
+        owner: provider.wallet.publicKey,
+        collectionPda: releasedCollectionPDA,
+        metadata: releasedCollectionMetadataPDA,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const collection = await program.account.collectionAccount.fetch(releasedCollectionPDA);
+    expect(true).equal(collection.updateAuthorityReleased);
+
+    const metaplex = Metaplex.make(provider.connection);
+    const nft = await metaplex.nfts().findByMint({ mintAddress: releasedCollectionTokenMint });
+    expect(newAuthorityKP.publicKey.toString()).equal(nft.updateAuthorityAddress.toString());
+
+    try {
+      await program.methods
+        .updateCollection('Released Collection', 'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG', 'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA')
+        .accounts({
+          owner: provider.wallet.publicKey,
+          collectionPda: releasedCollectionPDA,
+          metadata: releasedCollectionMetadataPDA,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      expect.fail('Released collections can not be updated');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('UpdateAuthorityReleased');
+    }
+
+    const releasedNftKP = Keypair.generate();
+    const releasedNftTokenMint = releasedNftKP.publicKey;
+
+    try {
+      await program.methods
+        .mintNftFromCollection(
+          'Released NFT',
+          'QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG',                   // IPFS Image Hash
+          'https://arweave.net/mF0bbubycS50wu2-WSkZoU2g5scupj0hfzk8eqFEtpA',  // Metadata URI
+          null,                                                               // Edition max supply
+          new BN(0),                                                          // Edition price
+        )
+        .accounts({
+          mint: releasedNftTokenMint,
+          mintAuthority: provider.wallet.publicKey,
+          payer: provider.wallet.publicKey,
+          rent: SYSVAR_RENT_PUBKEY,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenAccount: getAssociatedTokenAddressSync(releasedNftTokenMint, provider.wallet.publicKey),
+          associatedTokenProgram: SPL_ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          nftPda: getNftPDA(releasedCollectionPDA, releasedNftTokenMint),
+          masterEdition: getMasterEditionPDA(releasedNftTokenMint),
+          metadata: getMetadataPDA(releasedNftTokenMint),
+          collectionTokenMint: releasedCollectionTokenMint,
+          collectionPda: releasedCollectionPDA,
+          collectionMetadata: releasedCollectionMetadataPDA,
+          collectionMasterEd: getMasterEditionPDA(releasedCollectionTokenMint),
+          collectionAuthorityRecord: getCollectionAuthorityRecordPDA(releasedCollectionTokenMint, releasedCollectionPDA),
+        })
+        .preInstructions([modifyComputeUnits])
+        .signers([releasedNftKP])
+        .rpc();
+      expect.fail('Released collections can not mint');
+    } catch (err) {
+      expect(err.error.errorCode.code).equal('UpdateAuthorityReleased');
+    }
+  });
+
+});
+
+
+/* ******************************
+       BUILD METAPLEX PDAs
+****************************** */
+
+function getMetadataPDA(tokenMint: PublicKey): PublicKey {
+  const [metadataPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('metadata'),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        tokenMint.toBuffer(),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    );
+  return metadataPDA;
+}
+
+function getMasterEditionPDA(tokenMint: PublicKey): PublicKey {
+  const [masterEditionPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('metadata'),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        tokenMint.toBuffer(),
+        Buffer.from('edition'),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    );
+  return masterEditionPDA;
+}
+
+function getEditionMarkerPDA(tokenMint: PublicKey, edition: number): PublicKey {
+  const [editionMarkerPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('metadata'),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        tokenMint.toBuffer(),
+        Buffer.from('edition'),
+        Buffer.from(Math.floor(edition / 248).toString()),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    );
+  return editionMarkerPDA;
+}
+
+function getTokenRecordPDA(tokenMint: PublicKey, tokenAccount: PublicKey): PublicKey {
+  const [tokenRecordPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('metadata'),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        tokenMint.toBuffer(),
+        Buffer.from('token_record'),
+        tokenAccount.toBuffer(),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    );
+  return tokenRecordPDA;
+}
+
+function getCollectionAuthorityRecordPDA(collectionTokenMint: PublicKey, collectionPDA: PublicKey): PublicKey {
+  const [collectionAuthorityRecordPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('metadata'),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        collectionTokenMint.toBuffer(),
+        Buffer.from('collection_authority'),
+        collectionPDA.toBuffer(),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    );
+  return collectionAuthorityRecordPDA;
+}
+
+
+/* ******************************
+       BUILD CUSTOM PDAs
+****************************** */
+
+function getCollectionPDA(collectionTokenMint: PublicKey): PublicKey {
+  const [collectionPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('collection'),
+        collectionTokenMint.toBuffer(),
+      ],
+      program.programId
+    );
+  return collectionPDA;
+}
+
+function getNftPDA(collectionPDA: PublicKey, nftTokenMint: PublicKey): PublicKey {
+  const [nftPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('nft'),
+        collectionPDA.toBuffer(),
+        nftTokenMint.toBuffer(),
+      ],
+      program.programId
+    );
+  return nftPDA;
+}
+
+function getMinterPDA(collectionPDA: PublicKey, minter: PublicKey): PublicKey {
+  const [minterPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('minter'),
+        collectionPDA.toBuffer(),
+        minter.toBuffer(),
+      ],
+      program.programId
+    );
+  return minterPDA;
+}
+
+function getListingPDA(nftPDA: PublicKey): PublicKey {
+  const [listingPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('listing'),
+        nftPDA.toBuffer(),
+      ],
+      program.programId
+    );
+  return listingPDA;
+}
+
+function getEscrowPDA(nftPDA: PublicKey): PublicKey {
+  const [escrowPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('escrow'),
+        nftPDA.toBuffer(),
+      ],
+      program.programId
+    );
+  return escrowPDA;
+}
+
+function getMarketplacePDA(): PublicKey {
+  const [marketplacePDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('marketplace'),
+      ],
+      program.programId
+    );
+  return marketplacePDA;
+}
+
+function getProgramDataPDA(): PublicKey {
+  const [programDataPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        program.programId.toBuffer(),
+      ],
+      BPF_UPGRADEABLE_LOADER_PROGRAM_ID
+    );
+  return programDataPDA;
+}
+
+function getTreasuryPDA(): PublicKey {
+  const [treasuryPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('treasury'),
+      ],
+      program.programId
+    );
+  return treasuryPDA;
+}
+
+function getOfferPDA(nftPDA: PublicKey, bidder: PublicKey): PublicKey {
+  const [offerPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('offer'),
+        nftPDA.toBuffer(),
+        bidder.toBuffer(),
+      ],
+      program.programId
+    );
+  return offerPDA;
+}
+
+function getCollectionBidPDA(collectionPDA: PublicKey, bidder: PublicKey): PublicKey {
+  const [collectionBidPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('collection_bid'),
+        collectionPDA.toBuffer(),
+        bidder.toBuffer(),
+      ],
+      program.programId
+    );
+  return collectionBidPDA;
+}
+
+function getAuctionPDA(nftPDA: PublicKey): PublicKey {
+  const [auctionPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('auction'),
+        nftPDA.toBuffer(),
+      ],
+      program.programId
+    );
+  return auctionPDA;
+}
+
+function getAuctionEscrowPDA(nftPDA: PublicKey): PublicKey {
+  const [auctionEscrowPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('auction_escrow'),
+        nftPDA.toBuffer(),
+      ],
+      program.programId
+    );
+  return auctionEscrowPDA;
+}
+
+function getBidEscrowPDA(auctionPDA: PublicKey): PublicKey {
+  const [bidEscrowPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('bid_escrow'),
+        auctionPDA.toBuffer(),
+      ],
+      program.programId
+    );
+  return bidEscrowPDA;
+}
+
+// Hashes a pair of Merkle nodes in sorted order, like verify_merkle_proof
+function hashMerklePair(a: Buffer, b: Buffer): Buffer {
+  const [first, second] = Buffer.compare(a, b) <= 0 ? [a, b] : [b, a];
+  return Buffer.from(keccak_256(Buffer.concat([first, second])));
+}
+
+function getWalletMintsPDA(collectionPDA: PublicKey, wallet: PublicKey): PublicKey {
+  const [walletMintsPDA] = anchor.web3.PublicKey
+    .findProgramAddressSync(
+      [
+        Buffer.from('wallet_mints'),
+        collectionPDA.toBuffer(),
+        wallet.toBuffer(),
+      ],
+      program.programId
+    );
+  return walletMintsPDA;
 }